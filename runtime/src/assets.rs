@@ -0,0 +1,385 @@
+//! # Assets Module
+//!
+//!	A minimal module for fungible assets, such as project tokens airdropped by RedPackets.
+//!
+//!
+//! ## Overview
+//!
+//! Anyone can issue a new asset with a fixed supply, the whole supply is credited to the issuer.
+//! Asset `0` is the native currency, every other id is an asset issued by this module.
+//!
+//! Other modules hold and move assets through `MultiReservableCurrency`,
+//! which forwards the native asset to `Currency`.
+//!
+//! ## Interface
+//!
+//! ### Dispatchable Functions
+//!
+//! * `issue` - Issue a new asset, the issuer receives the whole supply.
+//! * `transfer` - Transfer some balance of an asset to another account.
+//!
+
+use frame_support::{
+	StorageValue, StorageMap, StorageDoubleMap,
+	decl_module, decl_storage, decl_event, decl_error,
+	dispatch::DispatchResult, Parameter,
+	ensure,
+	traits::{Currency, ReservableCurrency, ExistenceRequirement},
+	weights::SimpleDispatchInfo,
+};
+use system::ensure_signed;
+
+use sp_runtime::traits::{SimpleArithmetic, Zero, One};
+
+
+pub type BalanceOf<T> =
+	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
+
+pub trait Trait: system::Trait {
+	/// The overarching event type.
+	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
+
+	/// The native currency, asset `0`.
+	type Currency: ReservableCurrency<Self::AccountId>;
+
+	/// Identifier of an asset.
+	type AssetId: Parameter + SimpleArithmetic + Default + Copy;
+}
+
+/// A currency with several assets, each having free and reserved balances like `ReservableCurrency`.
+pub trait MultiReservableCurrency<AccountId> {
+	/// Identifier of an asset, the default one is the native currency.
+	type AssetId: Parameter + Default + Copy;
+
+	/// The balance of an account in any asset.
+	type Balance;
+
+	/// The free balance of `who` in `asset`.
+	fn free_balance(asset: Self::AssetId, who: &AccountId) -> Self::Balance;
+
+	/// The reserved balance of `who` in `asset`.
+	fn reserved_balance(asset: Self::AssetId, who: &AccountId) -> Self::Balance;
+
+	/// The smallest free balance an account can be created with in `asset`.
+	fn minimum_balance(asset: Self::AssetId) -> Self::Balance;
+
+	/// Move `value` of the free balance of `from` to `to`.
+	fn transfer(asset: Self::AssetId, from: &AccountId, to: &AccountId, value: Self::Balance) -> DispatchResult;
+
+	/// Move `value` of the free balance of `who` to its reserved balance.
+	fn reserve(asset: Self::AssetId, who: &AccountId, value: Self::Balance) -> DispatchResult;
+
+	/// Move up to `value` of the reserved balance of `who` back to its free balance.
+	fn unreserve(asset: Self::AssetId, who: &AccountId, value: Self::Balance);
+
+	/// Move `value` of the reserved balance of `from` to the free balance of `to`.
+	fn repatriate_reserved(
+		asset: Self::AssetId,
+		from: &AccountId,
+		to: &AccountId,
+		value: Self::Balance,
+	) -> DispatchResult;
+}
+
+// This module's storage items.
+decl_storage! {
+	trait Store for Module<T: Trait> as Assets {
+		/// Id of the last issued asset, `0` before any asset was issued.
+		pub LastAssetId get(fn last_asset_id): T::AssetId;
+
+		/// The total supply of an issued asset.
+		pub TotalSupply get(fn total_supply): map T::AssetId => BalanceOf<T>;
+
+		/// The free balance of an account in an issued asset.
+		pub FreeBalance get(fn balance):
+			double_map hasher(blake2_256) T::AssetId, blake2_256(T::AccountId) => BalanceOf<T>;
+
+		/// The reserved balance of an account in an issued asset.
+		pub ReservedBalance get(fn reserved):
+			double_map hasher(blake2_256) T::AssetId, blake2_256(T::AccountId) => BalanceOf<T>;
+	}
+}
+
+// The module's dispatchable functions.
+decl_module! {
+	/// The module declaration.
+	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
+		fn deposit_event() = default;
+
+		/// Issue a new asset, the sender receives the whole `total` supply.
+		#[weight = SimpleDispatchInfo::FixedNormal(10_000)]
+		fn issue(origin, total: BalanceOf<T>) -> DispatchResult {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(total > Zero::zero(), Error::<T>::GreaterThanZero);
+
+			let id = Self::last_asset_id() + One::one();
+
+			<LastAssetId<T>>::put(id);
+			<TotalSupply<T>>::insert(id, total);
+			<FreeBalance<T>>::insert(id, &sender, total);
+
+			Self::deposit_event(RawEvent::Issued(id, sender, total));
+
+			Ok(())
+		}
+
+		/// Transfer `value` of `asset` to `dest`.
+		#[weight = SimpleDispatchInfo::FixedNormal(10_000)]
+		fn transfer(origin, asset: T::AssetId, dest: T::AccountId, value: BalanceOf<T>) -> DispatchResult {
+			let sender = ensure_signed(origin)?;
+
+			<Self as MultiReservableCurrency<_>>::transfer(asset, &sender, &dest, value)?;
+
+			Self::deposit_event(RawEvent::Transferred(asset, sender, dest, value));
+
+			Ok(())
+		}
+	}
+}
+
+impl<T: Trait> MultiReservableCurrency<T::AccountId> for Module<T> {
+	type AssetId = T::AssetId;
+	type Balance = BalanceOf<T>;
+
+	fn free_balance(asset: T::AssetId, who: &T::AccountId) -> BalanceOf<T> {
+		if asset.is_zero() {
+			return T::Currency::free_balance(who);
+		}
+		Self::balance(asset, who)
+	}
+
+	fn reserved_balance(asset: T::AssetId, who: &T::AccountId) -> BalanceOf<T> {
+		if asset.is_zero() {
+			return T::Currency::reserved_balance(who);
+		}
+		Self::reserved(asset, who)
+	}
+
+	fn minimum_balance(asset: T::AssetId) -> BalanceOf<T> {
+		if asset.is_zero() {
+			return T::Currency::minimum_balance();
+		}
+		Zero::zero()
+	}
+
+	fn transfer(asset: T::AssetId, from: &T::AccountId, to: &T::AccountId, value: BalanceOf<T>) -> DispatchResult {
+		if asset.is_zero() {
+			return T::Currency::transfer(from, to, value, ExistenceRequirement::KeepAlive);
+		}
+
+		let from_balance = Self::balance(asset, from);
+		ensure!(from_balance >= value, Error::<T>::InsufficientBalance);
+
+		<FreeBalance<T>>::insert(asset, from, from_balance - value);
+		<FreeBalance<T>>::mutate(asset, to, |balance| *balance += value);
+
+		Ok(())
+	}
+
+	fn reserve(asset: T::AssetId, who: &T::AccountId, value: BalanceOf<T>) -> DispatchResult {
+		if asset.is_zero() {
+			return T::Currency::reserve(who, value);
+		}
+
+		let free = Self::balance(asset, who);
+		ensure!(free >= value, Error::<T>::InsufficientBalance);
+
+		<FreeBalance<T>>::insert(asset, who, free - value);
+		<ReservedBalance<T>>::mutate(asset, who, |reserved| *reserved += value);
+
+		Ok(())
+	}
+
+	fn unreserve(asset: T::AssetId, who: &T::AccountId, value: BalanceOf<T>) {
+		if asset.is_zero() {
+			T::Currency::unreserve(who, value);
+			return;
+		}
+
+		let reserved = Self::reserved(asset, who);
+		let actual = reserved.min(value);
+
+		<ReservedBalance<T>>::insert(asset, who, reserved - actual);
+		<FreeBalance<T>>::mutate(asset, who, |free| *free += actual);
+	}
+
+	fn repatriate_reserved(
+		asset: T::AssetId,
+		from: &T::AccountId,
+		to: &T::AccountId,
+		value: BalanceOf<T>,
+	) -> DispatchResult {
+		if asset.is_zero() {
+			return T::Currency::repatriate_reserved(from, to, value).map(|_| ());
+		}
+
+		let reserved = Self::reserved(asset, from);
+		ensure!(reserved >= value, Error::<T>::InsufficientBalance);
+
+		<ReservedBalance<T>>::insert(asset, from, reserved - value);
+		<FreeBalance<T>>::mutate(asset, to, |free| *free += value);
+
+		Ok(())
+	}
+}
+
+decl_event!(
+	pub enum Event<T>
+		where
+			AccountId = <T as system::Trait>::AccountId,
+			AssetId = <T as Trait>::AssetId,
+			Balance = BalanceOf<T>
+	{
+		/// A new asset was issued with its total supply.
+		Issued(AssetId, AccountId, Balance),
+
+		/// Some balance of an asset was transferred.
+		Transferred(AssetId, AccountId, AccountId, Balance),
+	}
+);
+
+decl_error! {
+	/// Error
+	pub enum Error for Module<T: Trait> {
+		/// Sender's balance is too low.
+		InsufficientBalance,
+		/// Parameter must be greater than zero
+		GreaterThanZero,
+	}
+}
+
+/// tests for this module
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use balances::GenesisConfig;
+	use frame_support::{impl_outer_origin, assert_ok, assert_noop, parameter_types, weights::Weight};
+	use sp_core::H256;
+	use sp_runtime::{Perbill, traits::{BlakeTwo256, IdentityLookup}, testing::Header};
+
+	impl_outer_origin! {
+		pub enum Origin for Test  {}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	parameter_types! {
+		pub const BlockHashCount: u64 = 250;
+		pub const MaximumBlockWeight: Weight = 1024;
+		pub const MaximumBlockLength: u32 = 2 * 1024;
+		pub const AvailableBlockRatio: Perbill = Perbill::one();
+	}
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type Call = ();
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type BlockHashCount = BlockHashCount;
+		type MaximumBlockWeight = MaximumBlockWeight;
+		type AvailableBlockRatio = AvailableBlockRatio;
+		type MaximumBlockLength = MaximumBlockLength;
+		type Version = ();
+		type ModuleToIndex = ();
+	}
+	parameter_types! {
+		pub const TransferFee: u64 = 0;
+		pub const CreationFee: u64 = 0;
+		pub const ExistentialDeposit: u64 = 0;
+	}
+	impl balances::Trait for Test {
+		type Balance = u64;
+		type OnFreeBalanceZero =  ();
+		type OnNewAccount = ();
+		type Event = ();
+		type TransferPayment = ();
+		type DustRemoval = ();
+		type ExistentialDeposit = ExistentialDeposit;
+		type TransferFee = TransferFee;
+		type CreationFee = CreationFee;
+	}
+	impl Trait for Test {
+		type Event = ();
+		type Currency = balances::Module<Self>;
+		type AssetId = u32;
+	}
+	type Assets = Module<Test>;
+
+	fn new_test_ext() -> sp_io::TestExternalities {
+		let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+		GenesisConfig::<Test> {
+			balances: vec![(1, 100), (2, 200)],
+			vesting: vec![]
+		}.assimilate_storage(&mut t).unwrap();
+		t.into()
+	}
+
+	#[test]
+	fn issue_should_work() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			assert_ok!(Assets::issue(Origin::signed(2), 500));
+
+			assert_eq!(Assets::last_asset_id(), 2);
+			assert_eq!(Assets::total_supply(1), 1_000);
+			assert_eq!(Assets::balance(1, 1), 1_000);
+			assert_eq!(Assets::balance(2, 2), 500);
+
+			assert_noop!(Assets::issue(Origin::signed(1), 0), Error::<Test>::GreaterThanZero);
+		});
+	}
+
+	#[test]
+	fn transfer_should_work() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+
+			assert_ok!(Assets::transfer(Origin::signed(1), 1, 3, 300));
+			assert_eq!(Assets::balance(1, 1), 700);
+			assert_eq!(Assets::balance(1, 3), 300);
+
+			assert_noop!(Assets::transfer(Origin::signed(3), 1, 1, 301), Error::<Test>::InsufficientBalance);
+		});
+	}
+
+	#[test]
+	fn reserve_and_repatriate_should_work() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+
+			assert_ok!(<Assets as MultiReservableCurrency<u64>>::reserve(1, &1, 600));
+			assert_eq!(Assets::balance(1, 1), 400);
+			assert_eq!(Assets::reserved(1, 1), 600);
+
+			assert_ok!(<Assets as MultiReservableCurrency<u64>>::repatriate_reserved(1, &1, &3, 100));
+			assert_eq!(Assets::reserved(1, 1), 500);
+			assert_eq!(Assets::balance(1, 3), 100);
+
+			// Unreserving more than reserved only frees the reserved balance.
+			<Assets as MultiReservableCurrency<u64>>::unreserve(1, &1, 1_000);
+			assert_eq!(Assets::reserved(1, 1), 0);
+			assert_eq!(Assets::balance(1, 1), 900);
+		});
+	}
+
+	#[test]
+	fn native_asset_should_use_currency() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(<Assets as MultiReservableCurrency<u64>>::reserve(0, &1, 60));
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 40);
+			assert_eq!(<Assets as MultiReservableCurrency<u64>>::reserved_balance(0, &1), 60);
+
+			assert_ok!(Assets::transfer(Origin::signed(2), 0, 1, 50));
+			assert_eq!(<Assets as MultiReservableCurrency<u64>>::free_balance(0, &1), 90);
+			assert_eq!(Assets::balance(0, 1), 0);
+		});
+	}
+}
@@ -11,7 +11,7 @@ include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 use sp_std::prelude::*;
 use sp_core::OpaqueMetadata;
 use sp_runtime::{
-	ApplyExtrinsicResult, transaction_validity::TransactionValidity, generic, create_runtime_str,
+	ApplyExtrinsicResult, transaction_validity::{TransactionValidity, TransactionPriority}, generic, create_runtime_str,
 	impl_opaque_keys, MultiSignature
 };
 use sp_runtime::traits::{
@@ -60,12 +60,23 @@ pub type Index = u32;
 /// A hash of some data used by the chain.
 pub type Hash = sp_core::H256;
 
+/// Identifier of a RedPacket.
+pub type PacketId = u32;
+
+/// A RedPacket as returned by `RedPacketApi`.
+pub type PacketInfo = redpacket::PacketOf<Runtime>;
+
 /// Digest item type.
 pub type DigestItem = generic::DigestItem<Hash>;
 
 /// Used for the module redpackage in `./redpackage.rs`
 mod redpacket;
 
+pub use redpacket::RedPacketApi;
+
+/// Used for the module assets in `./assets.rs`
+mod assets;
+
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
 /// of data like extrinsics, allowing for them to continue syncing the network through upgrades
@@ -95,7 +106,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("redpacket"),
 	impl_name: create_runtime_str!("redpacket"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 2,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
 };
@@ -204,9 +215,9 @@ impl balances::Trait for Runtime {
 	/// The type for recording an account's balance.
 	type Balance = Balance;
 	/// What to do if an account's free balance gets zeroed.
-	type OnFreeBalanceZero = ();
+	type OnFreeBalanceZero = RedPacket;
 	/// What to do if a new account is created.
-	type OnNewAccount = Indices;
+	type OnNewAccount = (Indices, RedPacket);
 	/// The ubiquitous event type.
 	type Event = Event;
 	type DustRemoval = ();
@@ -235,11 +246,55 @@ impl sudo::Trait for Runtime {
 	type Proposal = Call;
 }
 
+parameter_types! {
+	pub const MinimumShare: Balance = 500;
+	// At most 50 * PAYOUT_WEIGHT = 250_000 of `on_initialize`, a quarter of a block.
+	pub const MaxPayoutsPerBlock: u32 = 50;
+	// A batch with the largest bundle weighs 10_000 + 16 * 9 * 5_000 = 730_000,
+	// within the 75% of `MaximumBlockWeight` left to normal calls.
+	pub const MaxPayoutsPerCall: u32 = 16;
+	pub const MaxBundleSize: u32 = 8;
+	pub const FreeClaimInterval: BlockNumber = HOURS;
+	pub const MaxFreeClaimsPerBlock: u32 = 50;
+	pub const RedPacketUnsignedPriority: TransactionPriority = TransactionPriority::max_value() / 2;
+	pub const ExpectedBlockTime: u64 = MILLISECS_PER_BLOCK;
+	pub const ClaimPeriod: BlockNumber = DAYS;
+	pub const PacketDepositBase: Balance = 1_000;
+	pub const PacketDepositPerSlot: Balance = 100;
+	pub const RetentionPeriod: BlockNumber = 7 * DAYS;
+	pub const ReapReward: Balance = 500;
+}
+
+/// Used for the module assets in `./assets.rs`
+impl assets::Trait for Runtime {
+	type Event = Event;
+	type Currency = balances::Module<Runtime>;
+	type AssetId = u32;
+}
+
 /// Used for the module redpacket in `./redpacket.rs`
 impl redpacket::Trait for Runtime {
 	type Event = Event;
 	type Currency = balances::Module<Runtime>;
-	type PacketId = u32;
+	type Assets = Assets;
+	type PacketId = PacketId;
+	type Randomness = RandomnessCollectiveFlip;
+	type MinimumShare = MinimumShare;
+	type MaxPayoutsPerBlock = MaxPayoutsPerBlock;
+	type MaxPayoutsPerCall = MaxPayoutsPerCall;
+	type MaxBundleSize = MaxBundleSize;
+	type PacketDepositBase = PacketDepositBase;
+	type PacketDepositPerSlot = PacketDepositPerSlot;
+	type RetentionPeriod = RetentionPeriod;
+	type ReapReward = ReapReward;
+	type Signature = Signature;
+	type Signer = <Signature as Verify>::Signer;
+	type FreeClaimInterval = FreeClaimInterval;
+	type MaxFreeClaimsPerBlock = MaxFreeClaimsPerBlock;
+	type UnsignedPriority = RedPacketUnsignedPriority;
+	type Time = Timestamp;
+	type ExpectedBlockTime = ExpectedBlockTime;
+	type ClaimPeriod = ClaimPeriod;
 }
 
 construct_runtime!(
@@ -256,7 +311,8 @@ construct_runtime!(
 		Balances: balances,
 		TransactionPayment: transaction_payment::{Module, Storage},
 		Sudo: sudo,
-		RedPacket: redpacket::{Module, Call, Storage, Event<T>},
+		RedPacket: redpacket::{Module, Call, Storage, Config<T>, Event<T>, ValidateUnsigned},
+		Assets: assets::{Module, Call, Storage, Event<T>},
 		RandomnessCollectiveFlip: randomness_collective_flip::{Module, Call, Storage},
 	}
 );
@@ -278,7 +334,7 @@ pub type SignedExtra = (
 	system::CheckEra<Runtime>,
 	system::CheckNonce<Runtime>,
 	system::CheckWeight<Runtime>,
-	transaction_payment::ChargeTransactionPayment<Runtime>
+	redpacket::ChargeClaimFees<Runtime>
 );
 /// Unchecked extrinsic type as expected by this runtime.
 pub type UncheckedExtrinsic = generic::UncheckedExtrinsic<Address, Call, Signature, SignedExtra>;
@@ -366,4 +422,22 @@ impl_runtime_apis! {
 			Grandpa::grandpa_authorities()
 		}
 	}
+
+	impl redpacket::RedPacketApi<Block, AccountId, PacketId, Balance, PacketInfo> for Runtime {
+		fn packet(id: PacketId) -> Option<PacketInfo> {
+			RedPacket::packet(id)
+		}
+
+		fn claimable_amount(id: PacketId, who: AccountId) -> Option<Balance> {
+			RedPacket::claimable_amount(id, &who)
+		}
+
+		fn packets_by_owner(owner: AccountId) -> Vec<PacketId> {
+			RedPacket::packets_by_owner(&owner)
+		}
+
+		fn is_distributable(id: PacketId) -> bool {
+			RedPacket::is_distributable(id)
+		}
+	}
 }
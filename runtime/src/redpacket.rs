@@ -16,28 +16,82 @@
 //! ### Dispatchable Functions
 //!
 //! * `create` - Create a new RedPacket.
-//! * `claim` - Create a claiming record.
+//! * `create_lucky` - Create a new RedPacket that pays a random share to each claimer.
+//! * `create_merkle` - Create a new RedPacket committing to a Merkle root of `(account, amount)` leaves.
+//! * `claim` - Create a claiming record, or pay the claimer at once for `Immediate` RedPackets.
+//! * `claim_with_proof` - Claim the amount of a leaf from a Merkle RedPacket.
+//! * `claim_with_secret` - Claim from a RedPacket protected by a secret code.
+//! * `claim_unsigned` - Claim by an unsigned transaction, for accounts which can not pay any fee.
 //! * `distribute` - After a RedPacket was expired or finished, 
 //!    the RedPacket's creator can distribute to all claimed accounts.
+//! * `refund` - After a RedPacket was expired, the RedPacket's creator can take back the unclaimed balance.
+//! * `cancel` - The RedPacket's creator can withdraw it at any time, or close it early once claimed from.
+//! * `reap` - Remove a closed RedPacket and its claims from storage after `RetentionPeriod`.
 //!
 
 use frame_support::{
-	StorageValue, StorageMap, 
+	StorageValue, StorageMap, StorageDoubleMap,
 	decl_module, decl_storage, decl_event, decl_error,
-	dispatch::DispatchResult, Parameter,
+	dispatch::{DispatchResult, DispatchError, IsSubType}, Parameter,
+	storage,
+	unsigned::ValidateUnsigned,
 	ensure,
-	traits::{Currency, ReservableCurrency, ExistenceRequirement }
+	traits::{
+		Currency, ReservableCurrency, ExistenceRequirement, Get, Randomness, Time, OnNewAccount, OnFreeBalanceZero,
+	},
+	weights::{SimpleDispatchInfo, WeighData, ClassifyDispatch, DispatchClass, PaysFee, Weight, DispatchInfo},
 };
-use codec::{Encode, Decode};
-use system::ensure_signed;
+use codec::{Encode, Decode, Codec};
+#[cfg(feature = "std")]
+use serde::{Serialize, Deserialize};
+use system::{ensure_signed, ensure_none};
+
+use sp_runtime::traits::{
+	SimpleArithmetic, Zero, One, Saturating, UniqueSaturatedFrom, Hash as HashT, Verify, IdentifyAccount,
+	SignedExtension, UniqueSaturatedInto,
+};
+use sp_runtime::transaction_validity::{
+	TransactionValidity, TransactionValidityError, ValidTransaction, InvalidTransaction, TransactionPriority,
+};
+use transaction_payment::ChargeTransactionPayment;
+use sp_std::{prelude::*, marker::PhantomData};
 
-use sp_runtime::traits::{SimpleArithmetic, Zero, One, Saturating};
-use sp_std::{prelude::*};
+use crate::assets::MultiReservableCurrency;
 
 
 pub type BalanceOf<T> =
 	<<T as Trait>::Currency as Currency<<T as system::Trait>::AccountId>>::Balance;
 
+pub type AssetIdOf<T> =
+	<<T as Trait>::Assets as MultiReservableCurrency<<T as system::Trait>::AccountId>>::AssetId;
+
+pub type MomentOf<T> = <<T as Trait>::Time as Time>::Moment;
+
+/// Prefix of the message signed with the keypair of a secret code.
+pub const SECRET_CONTEXT: &[u8] = b"redpacket/secret";
+
+/// Prefix of the message signed by the claimer of an unsigned claim.
+pub const CLAIM_CONTEXT: &[u8] = b"redpacket/claim";
+
+/// Weight of calls touching a fixed number of storage items, about ten.
+///
+/// This version of FRAME has no benchmarking framework, weights count the storage items a call
+/// reads or writes, `1_000` each. `claim` reads and writes the RedPacket, the claim and the claims
+/// in period of the claimer, reads the distribution progress, the secret code, the rules and
+/// the recipient entry, and writes `Claimers` and `ClaimsByAccount`.
+/// Calls on unknown RedPackets fail before anything else, they weigh `BASE_WEIGHT` only.
+pub const BASE_WEIGHT: Weight = 10_000;
+
+/// Additional weight for every asset paid to a claimer: the reserved balance of the creator
+/// and the free balance of the claimer read and written, the free balance of the creator read.
+pub const PAYOUT_WEIGHT: Weight = 5_000;
+
+/// Additional weight for every recipient stored or removed, a single write.
+pub const RECIPIENT_WEIGHT: Weight = 1_000;
+
+/// Additional weight for every node of a Merkle proof hashed, about a storage access.
+pub const HASH_WEIGHT: Weight = 1_000;
+
 /// The module's configuration trait.
 pub trait Trait: system::Trait {
 	/// The overarching event type.
@@ -45,13 +99,205 @@ pub trait Trait: system::Trait {
 	
 	type Currency: ReservableCurrency<Self::AccountId>;
 
+	/// The assets RedPackets can hold, the default asset is the native currency.
+	type Assets: MultiReservableCurrency<Self::AccountId, Balance = BalanceOf<Self>>;
+
 	/// A u32 type 
 	type PacketId: Parameter + SimpleArithmetic + Default + Copy;
+
+	/// Source of randomness used to split lucky RedPackets.
+	type Randomness: Randomness<Self::Hash>;
+
+	/// The minimum amount every claim of a lucky RedPacket receives.
+	type MinimumShare: Get<BalanceOf<Self>>;
+
+	/// The maximum number of payouts made by the automatic settlement in a block,
+	/// every asset paid to a claimer counts as one.
+	///
+	/// `on_initialize` can not report its weight in this version of FRAME, so every RedPacket looked at
+	/// and every block passed counts as one as well. Keep `MaxPayoutsPerBlock * PAYOUT_WEIGHT`
+	/// well below `MaximumBlockWeight`.
+	type MaxPayoutsPerBlock: Get<u32>;
+
+	/// The maximum number of claims paid by a single call to `distribute`,
+	/// low enough for a batch with the largest bundle to fit in a block.
+	type MaxPayoutsPerCall: Get<u32>;
+
+	/// The maximum number of assets bundled with a RedPacket, besides its own asset.
+	type MaxBundleSize: Get<u32>;
+
+	/// The base deposit reserved for creating a RedPacket.
+	type PacketDepositBase: Get<BalanceOf<Self>>;
+
+	/// The deposit reserved for every slot of a RedPacket.
+	type PacketDepositPerSlot: Get<BalanceOf<Self>>;
+
+	/// Number of blocks a closed RedPacket is kept in storage before it can be reaped.
+	type RetentionPeriod: Get<Self::BlockNumber>;
+
+	/// The part of the deposit paid to the account reaping a RedPacket.
+	type ReapReward: Get<BalanceOf<Self>>;
+
+	/// Signature made with the keypair of a secret code.
+	type Signature: Parameter + Verify<Signer = Self::Signer>;
+
+	/// Public key of the keypair of a secret code.
+	type Signer: IdentifyAccount<AccountId = Self::AccountId>;
+
+	/// Number of blocks an account waits between two free claims.
+	type FreeClaimInterval: Get<Self::BlockNumber>;
+
+	/// The maximum number of free claims in a block.
+	type MaxFreeClaimsPerBlock: Get<u32>;
+
+	/// Priority of unsigned claims in the transaction pool.
+	type UnsignedPriority: Get<TransactionPriority>;
+
+	/// Wall-clock time RedPackets with a deadline expire at.
+	type Time: Time;
+
+	/// Expected time between two blocks, used to schedule the settlement of RedPackets with a deadline.
+	type ExpectedBlockTime: Get<MomentOf<Self>>;
+
+	/// Number of blocks the claims of an account are counted over, for `max_claims_per_period`.
+	type ClaimPeriod: Get<Self::BlockNumber>;
+}
+
+/// How the funds of a RedPacket are split between claimers.
+#[derive(Encode, Decode, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub enum PacketKind {
+	/// Every claimer receives `total / count`.
+	Average,
+	/// Every claimer receives a random share of the unclaimed balance,
+	/// the last claimer takes what is left.
+	Lucky,
+	/// Every claimer receives the amount of its leaf in a Merkle tree of `(account, amount)`.
+	Merkle,
+}
+
+impl Default for PacketKind {
+	fn default() -> Self {
+		PacketKind::Average
+	}
+}
+
+/// When claimers of a RedPacket receive their funds.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub enum PayoutMode {
+	/// Claims are recorded and paid in a batch by `distribute`.
+	Deferred,
+	/// Claims are paid out of the creator's reserve as soon as they are made.
+	Immediate,
+}
+
+impl Default for PayoutMode {
+	fn default() -> Self {
+		PayoutMode::Deferred
+	}
+}
+
+/// When a RedPacket opens for claims.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub enum Opening<BlockNumber, Moment> {
+	/// Claims are accepted from this block number on.
+	Block(BlockNumber),
+	/// Claims are accepted from this moment of `Time` on.
+	Moment(Moment),
 }
 
+/// Rules the claimers of a RedPacket must satisfy, zero disables a rule.
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Eligibility<Balance, BlockNumber> {
+	/// Minimum free native balance of a claimer.
+	pub min_balance: Balance,
+	/// Minimum number of blocks since the account of a claimer was created.
+	pub min_age: BlockNumber,
+	/// Maximum number of claims of a claimer in the current `ClaimPeriod`, this claim included.
+	pub max_claims_per_period: u32,
+}
+
+pub type EligibilityOf<T> = Eligibility<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+
+/// Optional settings of a new RedPacket.
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PacketOptions<AccountId, AssetId, Balance, BlockNumber, Moment> {
+	/// Asset held by the RedPacket, the native currency by default.
+	pub asset: AssetId,
+	/// Other assets and the quota of each paid with every claim, only for average RedPackets.
+	pub bundle: Vec<(AssetId, Balance)>,
+	/// Whether claimers are paid at once or by `distribute`.
+	pub payout: PayoutMode,
+	/// Accounts allowed to claim, anyone can claim if empty.
+	pub recipients: Vec<AccountId>,
+	/// Public key of the keypair derived from a secret code, required to claim if set.
+	pub secret: Option<AccountId>,
+	/// Moment of `Time` the RedPacket expires after, instead of a number of blocks.
+	pub deadline: Option<Moment>,
+	/// When claims are accepted from, at once if not set.
+	pub opens_at: Option<Opening<BlockNumber, Moment>>,
+	/// Rules against accounts farming the RedPacket.
+	pub eligibility: Eligibility<Balance, BlockNumber>,
+}
+
+pub type PacketOptionsOf<T> = PacketOptions<
+	<T as system::Trait>::AccountId,
+	AssetIdOf<T>,
+	BalanceOf<T>,
+	<T as system::Trait>::BlockNumber,
+	MomentOf<T>,
+>;
 
+pub type OpeningOf<T> = Opening<<T as system::Trait>::BlockNumber, MomentOf<T>>;
+
+/// A RedPacket, readable by other modules through `Module::packet`.
 #[derive(Encode, Decode, Default, Clone, PartialEq)]
-pub struct Packet<PacketId, Balance, BlockNumber, AccountId> {
+#[cfg_attr(feature = "std", derive(Debug, Serialize, Deserialize))]
+pub struct Packet<PacketId, Balance, BlockNumber, AccountId, AssetId, Moment> {
+	/// Id of the RedPacket.
+	pub id: PacketId,
+	/// How the funds are split between claimers.
+	pub kind: PacketKind,
+	/// Asset held by the RedPacket.
+	pub asset: AssetId,
+	/// Other assets and the quota of each paid with every claim.
+	pub bundle: Vec<(AssetId, Balance)>,
+	/// When claimers receive their funds.
+	pub payout: PayoutMode,
+	/// Amount of `asset` reserved for claimers.
+	pub total: Balance,
+	/// Amount of `asset` nobody claimed yet.
+	pub unclaimed: Balance,
+	/// Number of claims, or of leaves for Merkle RedPackets.
+	pub count: u32,
+	/// Number of claims made.
+	pub claimed: u32,
+	/// Whether only listed recipients can claim.
+	pub restricted: bool,
+	/// Last block number claims are accepted at.
+	/// With a deadline, the block number its settlement is scheduled at.
+	pub expires_at: BlockNumber,
+	/// Last moment claims are accepted at, replacing `expires_at` if set.
+	pub deadline: Option<Moment>,
+	/// When claims are accepted from, the funds are reserved from the creation on.
+	pub opens_at: Option<Opening<BlockNumber, Moment>>,
+	/// Creator of the RedPacket.
+	pub owner: AccountId,
+	/// Native deposit reserved for the storage of the RedPacket.
+	pub deposit: Balance,
+	/// Whether all claims were paid.
+	pub distributed: bool,
+	/// Block number the RedPacket was distributed or refunded at.
+	pub closed_at: Option<BlockNumber>,
+}
+
+/// A RedPacket in the layout before `PacketKind`, only read by `on_runtime_upgrade`.
+#[derive(Encode, Decode)]
+struct LegacyPacket<PacketId, Balance, BlockNumber, AccountId> {
 	id: PacketId,
 	total: Balance,
 	unclaimed: Balance,
@@ -61,18 +307,321 @@ pub struct Packet<PacketId, Balance, BlockNumber, AccountId> {
 	distributed: bool,
 }
 
+type LegacyPacketOf<T> = LegacyPacket<
+	<T as Trait>::PacketId,
+	BalanceOf<T>,
+	<T as system::Trait>::BlockNumber,
+	<T as system::Trait>::AccountId,
+>;
+
+pub type PacketOf<T> = Packet<
+	<T as Trait>::PacketId,
+	BalanceOf<T>,
+	<T as system::Trait>::BlockNumber,
+	<T as system::Trait>::AccountId,
+	AssetIdOf<T>,
+	MomentOf<T>,
+>;
+
+/// A claim of an account in a RedPacket.
+#[derive(Encode, Decode, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct ClaimInfo<Balance, BlockNumber> {
+	/// Amount the claimer receives.
+	pub amount: Balance,
+	/// Block number the claim was made at.
+	pub block: BlockNumber,
+}
+
+pub type ClaimInfoOf<T> = ClaimInfo<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+
+/// Weight of `create` and `create_lucky`, which store every listed recipient and bundled asset.
+pub struct CreateWeight<T>(PhantomData<T>);
+
+impl<T: Trait> Default for CreateWeight<T> {
+	fn default() -> Self {
+		CreateWeight(PhantomData)
+	}
+}
+
+impl<T: Trait> WeighData<(&BalanceOf<T>, &u32, &T::BlockNumber, &PacketOptionsOf<T>)> for CreateWeight<T> {
+	fn weigh_data(&self, (_, _, _, options): (&BalanceOf<T>, &u32, &T::BlockNumber, &PacketOptionsOf<T>)) -> Weight {
+		let slots = options.recipients.len().saturating_add(options.bundle.len()) as u32;
+		BASE_WEIGHT.saturating_add(RECIPIENT_WEIGHT.saturating_mul(Weight::from(slots)))
+	}
+}
+
+impl<T: Trait> ClassifyDispatch<(&BalanceOf<T>, &u32, &T::BlockNumber, &PacketOptionsOf<T>)> for CreateWeight<T> {
+	fn classify_dispatch(&self, _: (&BalanceOf<T>, &u32, &T::BlockNumber, &PacketOptionsOf<T>)) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl<T: Trait> PaysFee for CreateWeight<T> {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
+/// Weight of `claim`, `claim_with_secret`, `claim_unsigned` and `claim_with_proof`,
+/// which pay or check every asset of the bundle. `claim_with_proof` hashes every node of its proof as well.
+pub struct ClaimWeight<T>(PhantomData<T>);
+
+impl<T: Trait> Default for ClaimWeight<T> {
+	fn default() -> Self {
+		ClaimWeight(PhantomData)
+	}
+}
+
+impl<T: Trait> ClaimWeight<T> {
+	fn weigh(id: &T::PacketId) -> Weight {
+		let payouts = <Module<T>>::packets(id).map_or(0, |packet| (packet.bundle.len() as u32).saturating_add(1));
+		BASE_WEIGHT.saturating_add(PAYOUT_WEIGHT.saturating_mul(Weight::from(payouts)))
+	}
+}
+
+impl<T: Trait> WeighData<(&T::PacketId,)> for ClaimWeight<T> {
+	fn weigh_data(&self, (id,): (&T::PacketId,)) -> Weight {
+		Self::weigh(id)
+	}
+}
+
+impl<T: Trait> WeighData<(&T::PacketId, &T::Signature)> for ClaimWeight<T> {
+	fn weigh_data(&self, (id, _): (&T::PacketId, &T::Signature)) -> Weight {
+		Self::weigh(id)
+	}
+}
+
+impl<T: Trait> WeighData<(&T::PacketId, &T::AccountId, &T::Signature)> for ClaimWeight<T> {
+	fn weigh_data(&self, (id, _, _): (&T::PacketId, &T::AccountId, &T::Signature)) -> Weight {
+		Self::weigh(id)
+	}
+}
+
+impl<T: Trait> WeighData<(&T::PacketId, &u32, &BalanceOf<T>, &Vec<T::Hash>)> for ClaimWeight<T> {
+	fn weigh_data(&self, (id, _, _, proof): (&T::PacketId, &u32, &BalanceOf<T>, &Vec<T::Hash>)) -> Weight {
+		Self::weigh(id).saturating_add(HASH_WEIGHT.saturating_mul(proof.len() as Weight))
+	}
+}
+
+impl<T: Trait, Args> ClassifyDispatch<Args> for ClaimWeight<T> {
+	fn classify_dispatch(&self, _: Args) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl<T: Trait> PaysFee for ClaimWeight<T> {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
+/// Weight of `distribute` and `refund`.
+///
+/// The worst case is a batch paying `min(count, MaxPayoutsPerCall)` claims of the RedPacket,
+/// so huge RedPackets can not be settled for the price of a trivial call.
+/// Every claim pays each asset of the bundle as well.
+pub struct SettleWeight<T>(PhantomData<T>);
+
+impl<T: Trait> Default for SettleWeight<T> {
+	fn default() -> Self {
+		SettleWeight(PhantomData)
+	}
+}
+
+impl<T: Trait> WeighData<(&T::PacketId,)> for SettleWeight<T> {
+	fn weigh_data(&self, (id,): (&T::PacketId,)) -> Weight {
+		let payouts = <Module<T>>::packets(id).map_or(0, |packet| {
+			let assets = (packet.bundle.len() as u32).saturating_add(1);
+			packet.count.min(T::MaxPayoutsPerCall::get()).saturating_mul(assets)
+		});
+		BASE_WEIGHT.saturating_add(PAYOUT_WEIGHT.saturating_mul(Weight::from(payouts)))
+	}
+}
+
+impl<T: Trait> ClassifyDispatch<(&T::PacketId,)> for SettleWeight<T> {
+	fn classify_dispatch(&self, _: (&T::PacketId,)) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl<T: Trait> PaysFee for SettleWeight<T> {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
+/// Weight of `reap`, which removes every claim and listed recipient of the RedPacket.
+///
+/// Every claim removes its `ClaimOf`, `Claimers` and `ClaimsByAccount` entries and at most
+/// one word of `ClaimedBitmap`.
+pub struct ReapWeight<T>(PhantomData<T>);
+
+impl<T: Trait> Default for ReapWeight<T> {
+	fn default() -> Self {
+		ReapWeight(PhantomData)
+	}
+}
+
+impl<T: Trait> WeighData<(&T::PacketId,)> for ReapWeight<T> {
+	fn weigh_data(&self, (id,): (&T::PacketId,)) -> Weight {
+		let items = <Module<T>>::packets(id).map_or(0, |packet| {
+			packet.claimed.saturating_mul(4).saturating_add(<Module<T>>::recipient_count(id))
+		});
+		BASE_WEIGHT.saturating_add(RECIPIENT_WEIGHT.saturating_mul(Weight::from(items)))
+	}
+}
+
+impl<T: Trait> ClassifyDispatch<(&T::PacketId,)> for ReapWeight<T> {
+	fn classify_dispatch(&self, _: (&T::PacketId,)) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl<T: Trait> PaysFee for ReapWeight<T> {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
+/// Weight of `cancel`, which settles like `distribute` or removes every listed recipient
+/// of a RedPacket nobody claimed from.
+pub struct CancelWeight<T>(PhantomData<T>);
+
+impl<T: Trait> Default for CancelWeight<T> {
+	fn default() -> Self {
+		CancelWeight(PhantomData)
+	}
+}
+
+impl<T: Trait> WeighData<(&T::PacketId,)> for CancelWeight<T> {
+	fn weigh_data(&self, (id,): (&T::PacketId,)) -> Weight {
+		let recipients = Weight::from(<Module<T>>::recipient_count(id));
+		SettleWeight::<T>::default().weigh_data((id,)).saturating_add(RECIPIENT_WEIGHT.saturating_mul(recipients))
+	}
+}
+
+impl<T: Trait> ClassifyDispatch<(&T::PacketId,)> for CancelWeight<T> {
+	fn classify_dispatch(&self, _: (&T::PacketId,)) -> DispatchClass {
+		DispatchClass::Normal
+	}
+}
+
+impl<T: Trait> PaysFee for CancelWeight<T> {
+	fn pays_fee(&self) -> bool {
+		true
+	}
+}
+
 // This module's storage items.
 decl_storage! {
 	trait Store for Module<T: Trait> as RedPacket {
 
 		/// All packets.
-		pub Packets get(fn packets): map T::PacketId => Packet<T::PacketId, BalanceOf<T>, T::BlockNumber, T::AccountId>;
+		pub Packets get(fn packets): map T::PacketId => Option<PacketOf<T>>;
+
+		/// Claims of redpacket by id, in the layout used before `ClaimOf`.
+		/// Moved to `ClaimOf` and `Claimers` by `on_runtime_upgrade`.
+		Claims: map T::PacketId => Vec<T::AccountId>;
+
+		/// Get the claim of an account in a redpacket.
+		pub ClaimOf get(fn claim_of):
+			double_map hasher(blake2_256) T::PacketId, blake2_256(T::AccountId) => Option<ClaimInfoOf<T>>;
+
+		/// Claimers of a redpacket by claiming order, from `0` to `claimed - 1`.
+		pub Claimers get(fn claimer):
+			double_map hasher(blake2_256) T::PacketId, blake2_256(u32) => T::AccountId;
+
+		/// Merkle root of the `(account, amount)` leaves of a Merkle redpacket.
+		pub MerkleRoots get(fn merkle_root): map T::PacketId => T::Hash;
+
+		/// Claimed leaves of a Merkle redpacket, 32 leaves per word.
+		pub ClaimedBitmap get(fn claimed_bitmap):
+			double_map hasher(blake2_256) T::PacketId, blake2_256(u32) => u32;
+
+		/// Public key of the secret code of a redpacket.
+		pub Secrets get(fn secret_key): map T::PacketId => Option<T::AccountId>;
+
+		/// Eligibility rules of a redpacket, if it has any.
+		pub Rules get(fn eligibility): map T::PacketId => Option<EligibilityOf<T>>;
+
+		/// Block number an account was created at, for accounts created since this module tracks them.
+		pub AccountCreatedAt get(fn account_created_at): map T::AccountId => Option<T::BlockNumber>;
+
+		/// Block number `AccountCreatedAt` started tracking accounts at, the genesis or the runtime upgrade
+		/// adding it. The real age of older accounts is unknown, they are as old as the tracking.
+		pub AgeTrackingSince get(fn age_tracking_since): Option<T::BlockNumber>;
+
+		/// First block of the `ClaimPeriod` of the last claim of an account, and its number of claims in that period.
+		pub ClaimsInPeriod get(fn claims_in_period): map T::AccountId => (T::BlockNumber, u32);
+
+		/// Accounts allowed to claim from a restricted redpacket.
+		pub Recipients get(fn is_recipient):
+			double_map hasher(blake2_256) T::PacketId, blake2_256(T::AccountId) => bool;
+
+		/// Number of recipients listed for a restricted redpacket, to weigh their removal.
+		pub RecipientCount get(fn recipient_count): map T::PacketId => u32;
+
+		/// Whether RedPackets and their `Claims` were moved to the current layout.
+		PacketsMigrated: bool;
+
+		/// RedPackets created by an account, the value is the id of the RedPacket.
+		pub PacketsByOwner get(fn packet_of_owner):
+			double_map hasher(blake2_256) T::AccountId, blake2_256(T::PacketId) => Option<T::PacketId>;
 
-		/// Get claims of redpacket by id
-		pub Claims get(fn claims_of): map T::PacketId => Vec<T::AccountId>;
+		/// RedPackets an account claimed from, the value is the id of the RedPacket.
+		pub ClaimsByAccount get(fn claimed_packet):
+			double_map hasher(blake2_256) T::AccountId, blake2_256(T::PacketId) => Option<T::PacketId>;
+
+		/// Whether `PacketsByOwner` and `ClaimsByAccount` were built for existing RedPackets.
+		IndexesMigrated: bool;
 
 		/// The next package id.
 		pub NextPacketId get(next_packet_id): T::PacketId;
+
+		/// Ids of RedPackets by their `expires_at` block number and their position in that block.
+		pub Expirations get(fn expiration):
+			double_map hasher(blake2_256) T::BlockNumber, blake2_256(u32) => Option<T::PacketId>;
+
+		/// Number of RedPackets scheduled at a block number in `Expirations`.
+		pub ExpirationCount get(fn expiration_count): map T::BlockNumber => u32;
+
+		/// Block number and position in `Expirations` of the next RedPacket to settle automatically.
+		pub SettlementCursor get(fn settlement_cursor): (T::BlockNumber, u32);
+
+		/// Number of claims already paid and the amount paid to them, for RedPackets being distributed.
+		pub DistributionProgress get(fn distribution_progress): map T::PacketId => (u32, BalanceOf<T>);
+
+		/// Block number of the last free claim of an account.
+		pub LastFreeClaim get(fn last_free_claim): map T::AccountId => Option<T::BlockNumber>;
+
+		/// Number of free claims in the current block.
+		pub FreeClaimsInBlock get(fn free_claims_in_block): u32;
+	}
+	add_extra_genesis {
+		/// RedPackets created at genesis: owner, quota, count, expiry in blocks, and accounts which already claimed.
+		config(packets): Vec<(T::AccountId, BalanceOf<T>, u32, T::BlockNumber, Vec<T::AccountId>)>;
+		build(|config: &GenesisConfig<T>| {
+			for (owner, quota, count, expires, claimers) in config.packets.iter() {
+				let total = quota.saturating_mul(<BalanceOf<T>>::from(*count));
+				let id = <Module<T>>::do_create(owner.clone(), PacketKind::Average, Default::default(), total, *count, *expires)
+					.expect("genesis RedPacket owners are endowed with the total and the deposit; qed");
+				for claimer in claimers.iter() {
+					let packet = <Module<T>>::packets(id).expect("RedPacket was just created; qed");
+					<Module<T>>::ensure_claimable(claimer, id, &packet, None)
+						.expect("genesis claims are unique, not made by the owner and fit the count; qed");
+					let amount = <Module<T>>::claiming_amount(&packet, claimer)
+						.expect("genesis RedPackets are average RedPackets; qed");
+					<Module<T>>::record_claim(claimer.clone(), id, packet, amount)
+						.expect("genesis claimers are endowed and deferred claims are only recorded; qed");
+				}
+			}
+
+			// A new chain has nothing to migrate.
+			<PacketsMigrated>::put(true);
+			<IndexesMigrated>::put(true);
+			<AgeTrackingSince<T>>::put(T::BlockNumber::zero());
+		});
 	}
 }
 
@@ -84,161 +633,1321 @@ decl_module! {
 
 		fn deposit_event() = default;
 
+		fn on_runtime_upgrade() {
+			Self::migrate_packets();
+			Self::migrate_indexes();
+			Self::start_age_tracking();
+		}
+
+		fn on_initialize(n: T::BlockNumber) {
+			Self::settle_expired(n);
+		}
+
+		fn on_finalize() {
+			<FreeClaimsInBlock>::kill();
+		}
+
 		/// Create a new RedPacket
 		/// This will reserve balances(`quota` * `count`) of creator to prevent insufficient balance when distributing.
 		/// 
 		/// - `quota`: Amount per person will be received.
 		/// - `count`: Number of participants.
-		/// - `expires`: Expires after `expires` block number passed.
-		pub fn create(origin, quota: BalanceOf<T>, count: u32, expires: T::BlockNumber) -> DispatchResult {
+		/// - `expires`: Expires after `expires` block number passed, unless the options give a `deadline`.
+		/// - `options`: Payout mode and recipients of the RedPacket.
+		#[weight = CreateWeight::<T>::default()]
+		pub fn create(origin, quota: BalanceOf<T>, count: u32, expires: T::BlockNumber, options: PacketOptionsOf<T>) -> DispatchResult {
+			let sender = ensure_signed(origin)?;
 
-			ensure!(count > 0, Error::<T>::GreaterThanZero);
 			ensure!(quota > Zero::zero(), Error::<T>::GreaterThanZero);
-			ensure!(expires > Zero::zero(), Error::<T>::GreaterThanZero);
-
-			let sender = ensure_signed(origin)?;
 
 			let total = quota.saturating_mul(<BalanceOf<T>>::from(count));
 
-			let sender_balance = T::Currency::free_balance(&sender);
+			Self::do_create(sender, PacketKind::Average, options, total, count, expires).map(|_| ())
+		}
 
-			// Make sure sender has sufficient balance 
-			ensure!(sender_balance >= total, Error::<T>::InsufficientBalance);
+		/// Create a new lucky RedPacket
+		/// Each claimer receives a random share of the unclaimed balance, but at least `T::MinimumShare`.
+		/// The last claimer receives all the remaining balance.
+		///
+		/// - `total`: Total amount to be split between participants.
+		/// - `count`: Number of participants.
+		/// - `expires`: Expires after `expires` block number passed, unless the options give a `deadline`.
+		/// - `options`: Payout mode and recipients of the RedPacket.
+		#[weight = CreateWeight::<T>::default()]
+		pub fn create_lucky(origin, total: BalanceOf<T>, count: u32, expires: T::BlockNumber, options: PacketOptionsOf<T>) -> DispatchResult {
+			let sender = ensure_signed(origin)?;
 
-			// Reserve balance for RedPacket
-			T::Currency::reserve(&sender, total)?;
+			let minimum = T::MinimumShare::get().saturating_mul(<BalanceOf<T>>::from(count));
+			ensure!(total >= minimum, Error::<T>::BelowMinimumShare);
 
-			let current_block_number = <system::Module<T>>::block_number();
+			Self::do_create(sender, PacketKind::Lucky, options, total, count, expires).map(|_| ())
+		}
 
-			let expires_at = current_block_number + expires;
-			
-			let id = Self::next_packet_id();
-
-			let new_packet = Packet {
-				id: id,
-				total: total,
-				unclaimed: total,
-				count: count,
-				expires_at: expires_at,
-				owner: sender.clone(),
-				distributed: false, 
-			};
+		/// Create a new Merkle RedPacket
+		/// Claimers prove their `(account, amount)` leaf of the Merkle tree with `claim_with_proof`.
+		///
+		/// - `root`: Merkle root of the leaves, hashed with `T::Hashing`.
+		/// - `total`: Sum of the amounts of all leaves.
+		/// - `count`: Number of leaves.
+		/// - `expires`: Expires after `expires` block number passed, unless the options give a `deadline`.
+		/// - `options`: Payout mode of the RedPacket, the leaves already list its recipients.
+		#[weight = SimpleDispatchInfo::FixedNormal(BASE_WEIGHT)]
+		pub fn create_merkle(
+			origin,
+			root: T::Hash,
+			total: BalanceOf<T>,
+			count: u32,
+			expires: T::BlockNumber,
+			options: PacketOptionsOf<T>
+		) -> DispatchResult {
+			let sender = ensure_signed(origin)?;
 
-			<Packets<T>>::insert(id, new_packet);
+			// Leaves are bound to their account already, `claim_with_proof` takes no secret
+			// and no recipients are stored, so creating costs the same whatever the options.
+			ensure!(options.secret.is_none(), Error::<T>::UnsupportedKind);
+			ensure!(options.recipients.is_empty(), Error::<T>::UnsupportedKind);
 
-			<NextPacketId<T>>::mutate(|id| *id += One::one());
+			let id = Self::do_create(sender, PacketKind::Merkle, options, total, count, expires)?;
 
-			Self::deposit_event(RawEvent::Created(id, sender, total, count));
+			<MerkleRoots<T>>::insert(id, root);
 
 			Ok(())
 		}
 
 		/// Claim some amount from a RedPacket selected by id
+		/// For `Immediate` RedPackets the amount is moved from the creator's reserve to the claimer.
+		#[weight = ClaimWeight::<T>::default()]
 		fn claim(origin, packet_id: T::PacketId) -> DispatchResult {
 			let user = ensure_signed(origin)?;
 
-			let mut packet = Self::packets(packet_id);
+			let packet = Self::packets(packet_id).ok_or(Error::<T>::PacketNotFound)?;
 
-			let current_block_number = <system::Module<T>>::block_number();
+			Self::ensure_claimable(&user, packet_id, &packet, None)?;
+
+			let claiming_amount = Self::claiming_amount(&packet, &user)?;
+
+			Self::record_claim(user, packet_id, packet, claiming_amount)
+		}
+
+		/// Claim some amount from a RedPacket protected by a secret code
+		///
+		/// - `signature`: Signature of `(SECRET_CONTEXT, packet_id, claimer)` made with the keypair
+		///   derived from the secret code.
+		#[weight = ClaimWeight::<T>::default()]
+		fn claim_with_secret(origin, packet_id: T::PacketId, signature: T::Signature) -> DispatchResult {
+			let user = ensure_signed(origin)?;
+
+			let packet = Self::packets(packet_id).ok_or(Error::<T>::PacketNotFound)?;
+
+			Self::ensure_claimable(&user, packet_id, &packet, Some(&signature))?;
+
+			let claiming_amount = Self::claiming_amount(&packet, &user)?;
+
+			Self::record_claim(user, packet_id, packet, claiming_amount)
+		}
+
+		/// Claim some amount from a RedPacket selected by id for `claimer`, by an unsigned transaction
+		///
+		/// - `signature`: Signature of `(CLAIM_CONTEXT, packet_id, claimer)` made by `claimer`.
+		#[weight = ClaimWeight::<T>::default()]
+		fn claim_unsigned(
+			origin,
+			packet_id: T::PacketId,
+			claimer: T::AccountId,
+			signature: T::Signature,
+		) -> DispatchResult {
+			ensure_none(origin)?;
+
+			// `validate_unsigned` checks the limit against the pool, the block needs its own check.
+			ensure!(
+				Self::free_claims_in_block() < T::MaxFreeClaimsPerBlock::get(),
+				Error::<T>::FreeClaimsExhausted
+			);
+
+			let message = (CLAIM_CONTEXT, packet_id, &claimer).encode();
+			ensure!(signature.verify(&message[..], &claimer), Error::<T>::InvalidSignature);
+
+			let packet = Self::packets(packet_id).ok_or(Error::<T>::PacketNotFound)?;
+
+			Self::ensure_claimable(&claimer, packet_id, &packet, None)?;
+
+			let claiming_amount = Self::claiming_amount(&packet, &claimer)?;
+
+			Self::record_claim(claimer, packet_id, packet, claiming_amount)?;
+
+			<FreeClaimsInBlock>::mutate(|count| *count += 1);
+
+			Ok(())
+		}
+
+		/// Claim the amount of a leaf from a Merkle RedPacket selected by id
+		///
+		/// - `index`: Position of the leaf in the Merkle tree.
+		/// - `amount`: Amount of the leaf.
+		/// - `proof`: Sibling hashes from the leaf up to the root.
+		#[weight = ClaimWeight::<T>::default()]
+		fn claim_with_proof(origin, packet_id: T::PacketId, index: u32, amount: BalanceOf<T>, proof: Vec<T::Hash>) -> DispatchResult {
+			let user = ensure_signed(origin)?;
 
-			ensure!(current_block_number <= packet.expires_at , Error::<T>::Expired);
+			let packet = Self::packets(packet_id).ok_or(Error::<T>::PacketNotFound)?;
 
-			// Check RedPacket available
-			ensure!(packet.unclaimed > Zero::zero(), Error::<T>::Unavailable);
+			ensure!(packet.kind == PacketKind::Merkle, Error::<T>::UnsupportedKind);
 
-			let claims =  Self::claims_of(packet_id);
+			Self::ensure_claimable(&user, packet_id, &packet, None)?;
 
-			ensure!(!claims.contains(&user), Error::<T>::AlreadyClaimed);
+			// A proof of `n` hashes covers `2^n` leaves, so every leaf has exactly one index.
+			ensure!(index < packet.count, Error::<T>::InvalidProof);
+			ensure!(proof.len() <= 32 && u64::from(index) >> proof.len() == 0, Error::<T>::InvalidProof);
 
-			let claiming_amount = packet.total / <BalanceOf<T>>::from(packet.count);
+			let (word, bit) = (index / 32, 1u32 << (index % 32));
+			ensure!(Self::claimed_bitmap(&packet_id, &word) & bit == 0, Error::<T>::AlreadyClaimed);
 
-			packet.unclaimed -= claiming_amount;
+			ensure!(amount <= packet.unclaimed, Error::<T>::Unavailable);
 
-			<Packets<T>>::insert(packet_id, packet);
+			let leaf = T::Hashing::hash_of(&(&user, amount));
+			ensure!(
+				Self::verify_proof(Self::merkle_root(packet_id), leaf, index, &proof),
+				Error::<T>::InvalidProof
+			);
 
-			<Claims<T>>::mutate(packet_id, |claims| claims.push(user.clone()));
+			Self::record_claim(user, packet_id, packet, amount)?;
 
-			Self::deposit_event(RawEvent::Claimed(packet_id, user, claiming_amount));
+			<ClaimedBitmap<T>>::mutate(&packet_id, &word, |bits| *bits |= bit);
 
 			Ok(())
 		}
 
 		/// Distribute the RedPacket to claimers.
-		/// Iterate `Self::claims`, transfer balances of creator to each participant.
+		/// Iterate `Self::claimer`, transfer balances of creator to each participant,
+		/// then refund the unclaimed balance to the creator and close the RedPacket.
+		/// At most `MaxPayoutsPerCall` claims are paid per call, call again until `Distributed` is emitted.
+		#[weight = SettleWeight::<T>::default()]
 		fn distribute(origin, id: T::PacketId) -> DispatchResult {
 			let owner = ensure_signed(origin)?;
-			let mut packet = Self::packets(id);
+			let packet = Self::packets(id).ok_or(Error::<T>::PacketNotFound)?;
 
 			// Check owner
 			ensure!(packet.owner == owner, Error::<T>::NotOwner);
 			// Check distributed
 			ensure!(!packet.distributed, Error::<T>::AlreadyDistributed);
 
-			let current_block_number = <system::Module<T>>::block_number();
-
-			let expired = current_block_number > packet.expires_at;
+			let expired = Self::is_expired(&packet);
 			let finished = packet.unclaimed == Zero::zero();
+			let started = <DistributionProgress<T>>::exists(id);
 
-			// Redpacket can be distributed when expired or finished.
-			if expired || finished {
+			// Redpacket can be distributed when expired or finished,
+			// a distribution started by `cancel` can always be continued.
+			if expired || finished || started {
+
+				Self::settle(id, packet, T::MaxPayoutsPerCall::get());
+				Ok(())
 
-				// Unreserve balance of Redpacket for transfering
-				T::Currency::unreserve(&owner, packet.total);
+			} else {
+				Err(Error::<T>::CanNotBeDistributed)?
+			}
+		}
 
-				let mut total_distributed: BalanceOf<T> = Zero::zero();
+		/// Refund the unclaimed balance of an expired RedPacket to its creator and close it.
+		/// Claims which are not distributed yet are paid before refunding.
+		#[weight = SettleWeight::<T>::default()]
+		fn refund(origin, id: T::PacketId) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+			let packet = Self::packets(id).ok_or(Error::<T>::PacketNotFound)?;
 
-				let claims =  Self::claims_of(id);
-				let quota = packet.total / <BalanceOf<T>>::from(packet.count);
+			ensure!(packet.owner == owner, Error::<T>::NotOwner);
+			ensure!(packet.closed_at.is_none(), Error::<T>::AlreadyClosed);
 
-				// Update RedPacket first to prevent re-entry when error happened below loop logic
-				packet.distributed = true;
-				<Packets<T>>::insert(id, packet);
+			ensure!(Self::is_expired(&packet), Error::<T>::NotExpired);
 
-				for user in claims.into_iter(){
-					if user != owner {
-						<T::Currency>::transfer(&owner, &user, quota, ExistenceRequirement::KeepAlive)?;
-						total_distributed += quota;
-					}
-				}
+			Self::settle(id, packet, T::MaxPayoutsPerCall::get());
+			Ok(())
+		}
 
-				Self::deposit_event(RawEvent::Distributed(id, owner, total_distributed));
+		/// Withdraw a RedPacket before it expires.
+		/// A RedPacket nobody claimed from is removed and everything it reserved is returned to the creator.
+		/// Otherwise the RedPacket is closed early and settled with the existing claimers,
+		/// call `distribute` until `Distributed` is emitted if they do not fit in a single batch.
+		#[weight = CancelWeight::<T>::default()]
+		fn cancel(origin, id: T::PacketId) -> DispatchResult {
+			let owner = ensure_signed(origin)?;
+			let packet = Self::packets(id).ok_or(Error::<T>::PacketNotFound)?;
 
-				Ok(())
+			ensure!(packet.owner == owner, Error::<T>::NotOwner);
+			ensure!(packet.closed_at.is_none(), Error::<T>::AlreadyClosed);
 
-			} else {
-				Err(Error::<T>::CanNotBeDistributed)?
+			if packet.claimed > 0 {
+				Self::settle(id, packet, T::MaxPayoutsPerCall::get());
+				return Ok(());
+			}
+
+			// Without claims the whole quota of every slot is still reserved.
+			let count = <BalanceOf<T>>::from(packet.count);
+			Self::unreserve_all(&owner, &Self::bundle_payouts(packet.asset, &packet.bundle, packet.unclaimed, count));
+			T::Currency::unreserve(&owner, packet.deposit);
+
+			<Packets<T>>::remove(id);
+			<PacketsByOwner<T>>::remove(&owner, &id);
+			<Recipients<T>>::remove_prefix(&id);
+			<RecipientCount<T>>::remove(id);
+			<MerkleRoots<T>>::remove(id);
+			<Secrets<T>>::remove(id);
+			<Rules<T>>::remove(id);
+
+			Self::deposit_event(RawEvent::Cancelled(id, owner, packet.unclaimed));
+
+			Ok(())
+		}
+
+		/// Remove a closed RedPacket and its claims from storage.
+		/// Anyone can call this once `RetentionPeriod` blocks passed since the RedPacket was closed,
+		/// the caller receives `ReapReward` from the creator's deposit.
+		#[weight = ReapWeight::<T>::default()]
+		fn reap(origin, id: T::PacketId) -> DispatchResult {
+			let reaper = ensure_signed(origin)?;
+			let packet = Self::packets(id).ok_or(Error::<T>::PacketNotFound)?;
+
+			let closed_at = packet.closed_at.ok_or(Error::<T>::NotClosed)?;
+
+			let current_block_number = <system::Module<T>>::block_number();
+			ensure!(
+				current_block_number >= closed_at + T::RetentionPeriod::get(),
+				Error::<T>::StillRetained
+			);
+
+			let reward = T::ReapReward::get().min(packet.deposit);
+			Self::pay_deposit(&packet.owner, &reaper, reward)?;
+			T::Currency::unreserve(&packet.owner, packet.deposit - reward);
+
+			for index in 0..packet.claimed {
+				<ClaimsByAccount<T>>::remove(Self::claimer(&id, &index), &id);
 			}
+			<PacketsByOwner<T>>::remove(&packet.owner, &id);
+
+			<Packets<T>>::remove(id);
+			<ClaimOf<T>>::remove_prefix(&id);
+			<Claimers<T>>::remove_prefix(&id);
+			<Recipients<T>>::remove_prefix(&id);
+			<RecipientCount<T>>::remove(id);
+			<MerkleRoots<T>>::remove(id);
+			<Secrets<T>>::remove(id);
+			<Rules<T>>::remove(id);
+			<ClaimedBitmap<T>>::remove_prefix(&id);
+
+			Self::deposit_event(RawEvent::Reaped(id, reaper));
+
+			Ok(())
 		}
 	}
 }
 
-decl_event!(
-	pub enum Event<T> 
-		where 
-			AccountId = <T as system::Trait>::AccountId,
-			PacketId = <T as Trait>::PacketId,
-			Balance = BalanceOf<T>
-	{
-		/// A new RedPacket was created.
-		Created(PacketId, AccountId, Balance, u32),
+impl<T: Trait> Module<T> {
+	/// The RedPacket `id`, if it was created and not reaped yet.
+	pub fn packet(id: T::PacketId) -> Option<PacketOf<T>> {
+		Self::packets(id)
+	}
 
-		/// A new claim was created.
-		Claimed(PacketId, AccountId, Balance),
+	/// Claims of the RedPacket `id` in the order they were made, with the amount of each.
+	pub fn claims(id: T::PacketId) -> Vec<(T::AccountId, BalanceOf<T>)> {
+		let claimed = Self::packet(id).map_or(0, |packet| packet.claimed);
+		(0..claimed)
+			.map(|index| {
+				let user = Self::claimer(&id, &index);
+				let amount = Self::claim_of(&id, &user).map(|claim| claim.amount).unwrap_or_default();
+				(user, amount)
+			})
+			.collect()
+	}
 
-		/// Distribute the RedPacket to claimers.
-		Distributed(PacketId, AccountId, Balance),
+	/// Amount `who` receives by claiming from the RedPacket `id` now, if it can claim at all.
+	///
+	/// A lucky share is only drawn by the claim, the average of the remaining shares is returned instead.
+	/// The amount of a Merkle leaf comes with its proof, so none is returned. Secret codes are assumed known.
+	pub fn claimable_amount(id: T::PacketId, who: &T::AccountId) -> Option<BalanceOf<T>> {
+		let packet = Self::packet(id)?;
+		Self::ensure_eligible(who, id, &packet).ok()?;
+
+		match packet.kind {
+			PacketKind::Average => Some(packet.total / <BalanceOf<T>>::from(packet.count)),
+			PacketKind::Lucky => {
+				let remaining = packet.count.saturating_sub(packet.claimed).max(1);
+				Some(packet.unclaimed / <BalanceOf<T>>::from(remaining))
+			},
+			PacketKind::Merkle => None,
+		}
 	}
-);
 
-decl_error! {
-	/// Error
-	pub enum Error for Module<T: Trait> {
-		/// Sender's balance is too low.
-		InsufficientBalance,
-		/// Parameter must be greater than zero
-		GreaterThanZero,
-		/// RedPacket was Expired
-		Expired,
+	/// Ids of the RedPackets created by `owner` which were not reaped yet.
+	pub fn packets_by_owner(owner: &T::AccountId) -> Vec<T::PacketId> {
+		let mut ids = <PacketsByOwner<T>>::iter_prefix(owner).collect::<Vec<_>>();
+		ids.sort();
+		ids
+	}
+
+	/// Ids of the RedPackets `who` claimed from which were not reaped yet.
+	pub fn packets_claimed_by(who: &T::AccountId) -> Vec<T::PacketId> {
+		let mut ids = <ClaimsByAccount<T>>::iter_prefix(who).collect::<Vec<_>>();
+		ids.sort();
+		ids
+	}
+
+	/// Whether the owner of the RedPacket `id` can call `distribute` now.
+	pub fn is_distributable(id: T::PacketId) -> bool {
+		Self::packet(id).map_or(false, |packet| {
+			let expired = Self::is_expired(&packet);
+			let finished = packet.unclaimed == Zero::zero();
+			let started = <DistributionProgress<T>>::exists(id);
+			!packet.distributed && (expired || finished || started)
+		})
+	}
+
+	fn do_create(
+		sender: T::AccountId,
+		kind: PacketKind,
+		options: PacketOptionsOf<T>,
+		total: BalanceOf<T>,
+		count: u32,
+		expires: T::BlockNumber,
+	) -> Result<T::PacketId, DispatchError> {
+		ensure!(count > 0, Error::<T>::GreaterThanZero);
+		ensure!(total > Zero::zero(), Error::<T>::GreaterThanZero);
+		match options.deadline {
+			Some(deadline) => ensure!(deadline > T::Time::now(), Error::<T>::DeadlinePassed),
+			None => ensure!(expires > Zero::zero(), Error::<T>::GreaterThanZero),
+		}
+
+		ensure!(options.bundle.is_empty() || kind == PacketKind::Average, Error::<T>::UnsupportedKind);
+		ensure!(options.bundle.len() as u32 <= T::MaxBundleSize::get(), Error::<T>::BundleTooLarge);
+
+		let current_block_number = <system::Module<T>>::block_number();
+
+		// RedPackets with a deadline are settled around the block expected to pass it.
+		let expires_at = match options.deadline {
+			Some(deadline) => current_block_number + Self::blocks_until(deadline),
+			None => current_block_number + expires,
+		};
+
+		// Openings and expiries counted differently can not be compared.
+		match (options.opens_at, options.deadline) {
+			(Some(Opening::Block(opens_at)), None) => ensure!(opens_at <= expires_at, Error::<T>::InvalidOpening),
+			(Some(Opening::Moment(opens_at)), Some(deadline)) => ensure!(opens_at <= deadline, Error::<T>::InvalidOpening),
+			_ => {},
+		}
+
+		let asset = options.asset;
+
+		// Every asset is reserved once, so the balance checks below hold for the whole bundle.
+		let mut assets = Vec::with_capacity(options.bundle.len() + 1);
+		assets.push(asset);
+		for (bundled, quota) in options.bundle.iter() {
+			ensure!(!assets.contains(bundled), Error::<T>::DuplicateAsset);
+			ensure!(*quota > Zero::zero(), Error::<T>::GreaterThanZero);
+			assets.push(*bundled);
+		}
+
+		let slots = count
+			.saturating_add(options.recipients.len() as u32)
+			.saturating_add(options.bundle.len() as u32);
+		let deposit = T::PacketDepositBase::get()
+			.saturating_add(T::PacketDepositPerSlot::get().saturating_mul(<BalanceOf<T>>::from(slots)));
+
+		let reserves = Self::bundle_payouts(
+			asset,
+			&options.bundle,
+			total,
+			<BalanceOf<T>>::from(count),
+		);
+
+		// Make sure sender has sufficient balance 
+		for (asset, amount) in reserves.iter() {
+			ensure!(T::Assets::free_balance(*asset, &sender) >= *amount, Error::<T>::InsufficientBalance);
+		}
+		ensure!(T::Currency::free_balance(&sender) >= deposit, Error::<T>::InsufficientBalance);
+
+		// Reserve balances and storage deposit for RedPacket,
+		// the deposit may not fit any more when the RedPacket holds the native currency.
+		for (reserved, (asset, amount)) in reserves.iter().enumerate() {
+			if T::Assets::reserve(*asset, &sender, *amount).is_err() {
+				Self::unreserve_all(&sender, &reserves[..reserved]);
+				Err(Error::<T>::InsufficientBalance)?
+			}
+		}
+		if T::Currency::reserve(&sender, deposit).is_err() {
+			Self::unreserve_all(&sender, &reserves);
+			Err(Error::<T>::InsufficientBalance)?
+		}
+
+		let id = Self::next_packet_id();
+
+		let new_packet = Packet {
+			id: id,
+			kind: kind,
+			asset: asset,
+			bundle: options.bundle,
+			payout: options.payout,
+			total: total,
+			unclaimed: total,
+			count: count,
+			claimed: 0,
+			restricted: !options.recipients.is_empty(),
+			expires_at: expires_at,
+			deadline: options.deadline,
+			opens_at: options.opens_at,
+			owner: sender.clone(),
+			deposit: deposit,
+			distributed: false, 
+			closed_at: None,
+		};
+
+		<Packets<T>>::insert(id, new_packet);
+		<PacketsByOwner<T>>::insert(&sender, &id, id);
+
+		for recipient in options.recipients.iter() {
+			<Recipients<T>>::insert(&id, recipient, true);
+		}
+		if !options.recipients.is_empty() {
+			<RecipientCount<T>>::insert(id, options.recipients.len() as u32);
+		}
+
+		if let Some(key) = options.secret {
+			<Secrets<T>>::insert(id, key);
+		}
+
+		if options.eligibility != Default::default() {
+			<Rules<T>>::insert(id, options.eligibility);
+		}
+
+		Self::schedule(expires_at, id);
+
+		<NextPacketId<T>>::mutate(|id| *id += One::one());
+
+		Self::deposit_event(RawEvent::Created(id, sender, asset, total, count));
+
+		Ok(id)
+	}
+
+	/// Check `user` can claim from the RedPacket.
+	/// RedPackets protected by a secret code require a `signature` made with its keypair.
+	fn ensure_claimable(
+		user: &T::AccountId,
+		id: T::PacketId,
+		packet: &PacketOf<T>,
+		signature: Option<&T::Signature>,
+	) -> DispatchResult {
+		Self::ensure_eligible(user, id, packet)?;
+
+		if let Some(key) = Self::secret_key(id) {
+			let message = (SECRET_CONTEXT, id, user).encode();
+			let valid = signature.map_or(false, |signature| signature.verify(&message[..], &key));
+			ensure!(valid, Error::<T>::InvalidSecret);
+		}
+
+		Ok(())
+	}
+
+	/// Check `user` can claim from the RedPacket, leaving its secret code aside.
+	fn ensure_eligible(user: &T::AccountId, id: T::PacketId, packet: &PacketOf<T>) -> DispatchResult {
+		ensure!(!Self::is_expired(packet), Error::<T>::Expired);
+
+		ensure!(Self::is_open(packet), Error::<T>::NotYetOpen);
+
+		ensure!(packet.closed_at.is_none(), Error::<T>::AlreadyClosed);
+
+		// Claims paid in batches are final, the RedPacket closes with the last batch.
+		ensure!(!<DistributionProgress<T>>::exists(id), Error::<T>::AlreadyClosed);
+
+		// Check RedPacket available
+		ensure!(packet.unclaimed > Zero::zero(), Error::<T>::Unavailable);
+
+		ensure!(!<ClaimOf<T>>::exists(&id, user), Error::<T>::AlreadyClaimed);
+
+		// A claim of the creator would only burn a slot, its amount stays with the creator.
+		ensure!(user != &packet.owner, Error::<T>::OwnerCannotClaim);
+
+		ensure!(!packet.restricted || Self::is_recipient(&id, user), Error::<T>::NotEligible);
+
+		if let Some(rules) = Self::eligibility(id) {
+			ensure!(T::Currency::free_balance(user) >= rules.min_balance, Error::<T>::BalanceTooLow);
+			ensure!(Self::account_age(user) >= rules.min_age, Error::<T>::AccountTooNew);
+			ensure!(
+				rules.max_claims_per_period == 0 || Self::recent_claims(user) < rules.max_claims_per_period,
+				Error::<T>::TooManyClaims
+			);
+		}
+
+		Ok(())
+	}
+
+	/// Number of blocks since the account `who` was created.
+	///
+	/// Accounts without a balance do not exist yet. Accounts created before `AccountCreatedAt`
+	/// tracked them are as old as the tracking.
+	fn account_age(who: &T::AccountId) -> T::BlockNumber {
+		let now = <system::Module<T>>::block_number();
+		match Self::account_created_at(who) {
+			Some(created_at) => now.saturating_sub(created_at),
+			None if T::Currency::total_balance(who).is_zero() => Zero::zero(),
+			None => now.saturating_sub(Self::age_tracking_since().unwrap_or_else(Zero::zero)),
+		}
+	}
+
+	/// First block of the current `ClaimPeriod`.
+	fn period_start() -> T::BlockNumber {
+		let now = <system::Module<T>>::block_number();
+		now - now % T::ClaimPeriod::get().max(One::one())
+	}
+
+	/// Number of claims of `who` in the current `ClaimPeriod`.
+	fn recent_claims(who: &T::AccountId) -> u32 {
+		let (start, count) = Self::claims_in_period(who);
+		if start == Self::period_start() { count } else { 0 }
+	}
+
+	/// Whether claims of the RedPacket are over, after its deadline if it has one or after `expires_at` otherwise.
+	fn is_expired(packet: &PacketOf<T>) -> bool {
+		match packet.deadline {
+			Some(deadline) => T::Time::now() > deadline,
+			None => <system::Module<T>>::block_number() > packet.expires_at,
+		}
+	}
+
+	/// Whether the opening of the RedPacket was reached.
+	fn is_open(packet: &PacketOf<T>) -> bool {
+		match packet.opens_at {
+			Some(Opening::Block(opens_at)) => <system::Module<T>>::block_number() >= opens_at,
+			Some(Opening::Moment(opens_at)) => T::Time::now() >= opens_at,
+			None => true,
+		}
+	}
+
+	/// Number of blocks expected to pass until `deadline`, at least one.
+	fn blocks_until(deadline: MomentOf<T>) -> T::BlockNumber {
+		let remaining = deadline.saturating_sub(T::Time::now());
+		let block_time = T::ExpectedBlockTime::get().max(One::one());
+		let blocks: u64 = (remaining / block_time).unique_saturated_into();
+		T::BlockNumber::unique_saturated_from(blocks).saturating_add(One::one())
+	}
+
+	/// Amount `user` receives from an average or lucky RedPacket.
+	fn claiming_amount(packet: &PacketOf<T>, user: &T::AccountId) -> Result<BalanceOf<T>, DispatchError> {
+		match packet.kind {
+			PacketKind::Average => Ok(packet.total / <BalanceOf<T>>::from(packet.count)),
+			PacketKind::Lucky => Ok(Self::lucky_share(packet, user)),
+			PacketKind::Merkle => Err(Error::<T>::UnsupportedKind)?,
+		}
+	}
+
+	/// Every asset a claim of `amount` by `user` pays, as legs for `ensure_payable`.
+	fn claim_legs(
+		packet: &PacketOf<T>,
+		user: &T::AccountId,
+		amount: BalanceOf<T>,
+	) -> Vec<(T::AccountId, AssetIdOf<T>, BalanceOf<T>)> {
+		Self::bundle_payouts(packet.asset, &packet.bundle, amount, One::one())
+			.into_iter()
+			.map(|(asset, amount)| (user.clone(), asset, amount))
+			.collect()
+	}
+
+	/// Run every check of a claim of the RedPacket `id` by `user` without writing anything,
+	/// for claims which are free if they succeed.
+	fn check_claim(
+		user: &T::AccountId,
+		id: T::PacketId,
+		packet: &PacketOf<T>,
+		signature: Option<&T::Signature>,
+	) -> DispatchResult {
+		Self::ensure_claimable(user, id, packet, signature)?;
+		let amount = Self::claiming_amount(packet, user)?;
+		Self::ensure_payable(&packet.owner, &Self::claim_legs(packet, user, amount))
+	}
+
+	/// Record a claim of `amount` by `user`.
+	/// For `Immediate` RedPackets the amount and the bundle are moved from the creator's reserve to the claimer.
+	fn record_claim(
+		user: T::AccountId,
+		id: T::PacketId,
+		mut packet: PacketOf<T>,
+		amount: BalanceOf<T>,
+	) -> DispatchResult {
+		// Deferred claims are checked as well, so they do not hold up the settlement later.
+		let legs = Self::claim_legs(&packet, &user, amount);
+		Self::ensure_payable(&packet.owner, &legs)?;
+
+		if packet.payout == PayoutMode::Immediate {
+			Self::pay_all_from_reserve(&packet.owner, &legs)?;
+		}
+
+		let index = packet.claimed;
+
+		packet.unclaimed -= amount;
+		packet.claimed += 1;
+
+		<Packets<T>>::insert(id, packet);
+
+		<ClaimOf<T>>::insert(&id, &user, ClaimInfo {
+			amount: amount,
+			block: <system::Module<T>>::block_number(),
+		});
+		<Claimers<T>>::insert(&id, &index, &user);
+		<ClaimsByAccount<T>>::insert(&user, &id, id);
+		<ClaimsInPeriod<T>>::insert(&user, (Self::period_start(), Self::recent_claims(&user) + 1));
+
+		Self::deposit_event(RawEvent::Claimed(id, user, amount));
+
+		Ok(())
+	}
+
+	/// Check `proof` leads from `leaf` at `index` to `root`.
+	/// At every level the node is hashed after its sibling if the index bit is set, before it otherwise.
+	fn verify_proof(root: T::Hash, leaf: T::Hash, index: u32, proof: &[T::Hash]) -> bool {
+		let mut node = leaf;
+		let mut index = index;
+		for sibling in proof.iter() {
+			node = if index % 2 == 0 {
+				T::Hashing::hash_of(&(node, sibling))
+			} else {
+				T::Hashing::hash_of(&(sibling, node))
+			};
+			index /= 2;
+		}
+		node == root
+	}
+
+	/// Pay the next batch of claims of a RedPacket, at most `max_claims` of them.
+	/// After the last batch the unclaimed balance is refunded to the creator and the RedPacket is closed.
+	/// Claims of the creator itself are not paid, they can only exist in RedPackets created before
+	/// `OwnerCannotClaim`, and are refunded with the unclaimed balance.
+	///
+	/// A claim which can not be paid, e.g. to an account reaped below the existential deposit since,
+	/// is returned to the creator with `Forfeited`, so it does not hold up the other claimers.
+	/// The progress of the RedPacket is written once the batch was paid.
+	///
+	/// Returns `true` if the RedPacket was closed.
+	fn settle(id: T::PacketId, mut packet: PacketOf<T>, max_claims: u32) -> bool {
+		let owner = packet.owner.clone();
+		let asset = packet.asset;
+		let bundle = packet.bundle.clone();
+		let deferred = packet.payout == PayoutMode::Deferred;
+		let refund = packet.unclaimed;
+		let claimed = packet.claimed;
+		let packet_count = packet.count;
+
+		let (start, paid) = Self::distribution_progress(id);
+		let end = claimed.min(start.saturating_add(max_claims));
+		let finished = end == claimed;
+
+		let batch = (start..end)
+			.map(|index| {
+				let user = Self::claimer(&id, &index);
+				let amount = Self::claim_of(&id, &user).map(|claim| claim.amount).unwrap_or_default();
+				(user, amount)
+			})
+			.collect::<Vec<_>>();
+
+		// Claims are paid before the progress is written, dispatches are not rolled back on failure.
+		let mut forfeited_amount: BalanceOf<T> = Zero::zero();
+		for (user, amount) in batch.iter().filter(|_| deferred) {
+			let payouts = Self::bundle_payouts(asset, &bundle, *amount, One::one());
+			if user == &owner {
+				Self::unreserve_all(&owner, &payouts);
+				continue;
+			}
+
+			let legs = payouts.iter().map(|(asset, amount)| (user.clone(), *asset, *amount)).collect::<Vec<_>>();
+			// A claim whose payment failed had none of its legs paid, see `pay_all_from_reserve`.
+			let payment = Self::ensure_payable(&owner, &legs).and_then(|_| Self::pay_all_from_reserve(&owner, &legs));
+			if payment.is_err() {
+				Self::unreserve_all(&owner, &payouts);
+				forfeited_amount += *amount;
+				Self::deposit_event(RawEvent::Forfeited(id, user.clone(), *amount));
+			}
+		}
+
+		// Claims of `Immediate` RedPackets were already paid out of the reserve.
+		let batch_amount = batch.iter()
+			.filter(|(user, _)| user != &owner)
+			.fold(Zero::zero(), |sum: BalanceOf<T>, (_, amount)| sum + *amount)
+			- forfeited_amount;
+		let total_distributed = paid + batch_amount;
+
+		if finished {
+			packet.distributed = true;
+			packet.unclaimed = Zero::zero();
+			packet.closed_at = Some(<system::Module<T>>::block_number());
+			<Packets<T>>::insert(id, packet);
+			<DistributionProgress<T>>::remove(id);
+		} else {
+			<DistributionProgress<T>>::insert(id, (end, total_distributed));
+		}
+
+		if !finished {
+			let remaining = claimed - end;
+			Self::deposit_event(RawEvent::PartiallyDistributed(id, owner, batch_amount, remaining));
+			return false;
+		}
+
+		// Quotas of the bundle for the slots nobody claimed.
+		let unclaimed_slots = <BalanceOf<T>>::from(packet_count - claimed);
+		Self::unreserve_all(&owner, &Self::bundle_payouts(asset, &bundle, refund, unclaimed_slots));
+
+		// Claims the creator made before they were forbidden went back to the creator above,
+		// they are refunded as well so `Distributed` and `Refunded` add up to the total.
+		let owner_claim = Self::claim_of(&id, &owner).map(|claim| claim.amount).unwrap_or_default();
+		let refunded = refund + owner_claim;
+
+		Self::deposit_event(RawEvent::Distributed(id, owner.clone(), total_distributed));
+
+		if !refunded.is_zero() {
+			Self::deposit_event(RawEvent::Refunded(id, owner, refunded));
+		}
+
+		true
+	}
+
+	/// Move RedPackets and their claims from the layout before `PacketKind` to the current one.
+	///
+	/// Old RedPackets were average RedPackets of the native currency with deferred payouts
+	/// and no deposit, every claim received `total / count`. The block number of migrated claims
+	/// is unknown, it is set to zero. Distributed RedPackets released their reserve already,
+	/// they are closed at the upgrade. Open ones are scheduled for the automatic settlement,
+	/// the expired ones in the next block.
+	fn migrate_packets() {
+		if <PacketsMigrated>::get() {
+			return;
+		}
+
+		let now = <system::Module<T>>::block_number();
+		let next = Self::next_packet_id();
+		let mut id: T::PacketId = Zero::zero();
+		while id < next {
+			let raw = storage::unhashed::get_raw(&<Packets<T>>::hashed_key_for(id));
+			// RedPackets in the current layout are longer, they never decode as a whole.
+			let legacy = raw.and_then(|raw| {
+				let mut input = &raw[..];
+				LegacyPacketOf::<T>::decode(&mut input).ok().filter(|_| input.is_empty())
+			});
+
+			if let Some(legacy) = legacy {
+				let claims = <Claims<T>>::take(id);
+				let amount = legacy.total / <BalanceOf<T>>::from(legacy.count.max(1));
+				for (index, user) in claims.iter().enumerate() {
+					<ClaimOf<T>>::insert(&id, user, ClaimInfo {
+						amount: amount,
+						block: Zero::zero(),
+					});
+					<Claimers<T>>::insert(&id, &(index as u32), user);
+				}
+
+				<Packets<T>>::insert(id, Packet {
+					id: legacy.id,
+					kind: PacketKind::Average,
+					asset: Default::default(),
+					bundle: Vec::new(),
+					payout: PayoutMode::Deferred,
+					total: legacy.total,
+					unclaimed: if legacy.distributed { Zero::zero() } else { legacy.unclaimed },
+					count: legacy.count,
+					claimed: claims.len() as u32,
+					restricted: false,
+					expires_at: legacy.expires_at,
+					deadline: None,
+					opens_at: None,
+					owner: legacy.owner,
+					deposit: Zero::zero(),
+					distributed: legacy.distributed,
+					closed_at: if legacy.distributed { Some(now) } else { None },
+				});
+
+				if !legacy.distributed {
+					Self::schedule(legacy.expires_at.max(now), id);
+				}
+			}
+
+			id += One::one();
+		}
+
+		// Nothing was scheduled before, the automatic settlement starts at the upgrade.
+		<SettlementCursor<T>>::put((now, 0));
+		<PacketsMigrated>::put(true);
+	}
+
+	/// Start tracking account ages on a chain which did not track them yet.
+	fn start_age_tracking() {
+		if Self::age_tracking_since().is_none() {
+			<AgeTrackingSince<T>>::put(<system::Module<T>>::block_number());
+		}
+	}
+
+	/// Build `PacketsByOwner` and `ClaimsByAccount` for the RedPackets created before them.
+	fn migrate_indexes() {
+		if <IndexesMigrated>::get() {
+			return;
+		}
+
+		let next = Self::next_packet_id();
+		let mut id: T::PacketId = Zero::zero();
+		while id < next {
+			if let Some(packet) = Self::packet(id) {
+				<PacketsByOwner<T>>::insert(&packet.owner, &id, id);
+				for index in 0..packet.claimed {
+					<ClaimsByAccount<T>>::insert(Self::claimer(&id, &index), &id, id);
+				}
+			}
+			id += One::one();
+		}
+
+		<IndexesMigrated>::put(true);
+	}
+
+	/// Move `amount` of `asset` from the reserved balance of `owner` to `user`.
+	/// `repatriate_reserved` requires `user` to exist, so new accounts are created by a transfer.
+	/// A failed transfer, e.g. of less than the existential deposit, leaves `amount` reserved.
+	fn pay_from_reserve(
+		asset: AssetIdOf<T>,
+		owner: &T::AccountId,
+		user: &T::AccountId,
+		amount: BalanceOf<T>,
+	) -> DispatchResult {
+		if T::Assets::free_balance(asset, user).is_zero() {
+			T::Assets::unreserve(asset, owner, amount);
+			T::Assets::transfer(asset, owner, user, amount).map_err(|e| {
+				// Nothing was moved, the amount just unreserved is still free.
+				let reserved = T::Assets::reserve(asset, owner, amount);
+				debug_assert!(reserved.is_ok(), "amount was unreserved right before; qed");
+				e
+			})
+		} else {
+			T::Assets::repatriate_reserved(asset, owner, user, amount)
+		}
+	}
+
+	/// Amounts of every asset paid for `slots` claims of `amount`, the asset of the RedPacket first.
+	fn bundle_payouts(
+		asset: AssetIdOf<T>,
+		bundle: &[(AssetIdOf<T>, BalanceOf<T>)],
+		amount: BalanceOf<T>,
+		slots: BalanceOf<T>,
+	) -> Vec<(AssetIdOf<T>, BalanceOf<T>)> {
+		let mut payouts = Vec::with_capacity(bundle.len() + 1);
+		payouts.push((asset, amount));
+		payouts.extend(bundle.iter().map(|(asset, quota)| (*asset, quota.saturating_mul(slots))));
+		payouts
+	}
+
+	/// Pay every leg of `legs` from the reserve of `owner`, once `ensure_payable` checked them.
+	///
+	/// Once checked, only legs of the native currency can still fail, through transfer fees or locks.
+	/// A claim has at most one of them and it is paid first, so a claim whose payment fails
+	/// has none of its legs paid.
+	fn pay_all_from_reserve(
+		owner: &T::AccountId,
+		legs: &[(T::AccountId, AssetIdOf<T>, BalanceOf<T>)],
+	) -> DispatchResult {
+		let native = AssetIdOf::<T>::default();
+		let (first, rest): (Vec<_>, Vec<_>) = legs.iter().partition(|(_, asset, _)| *asset == native);
+
+		for (user, asset, amount) in first.into_iter().chain(rest) {
+			Self::pay_from_reserve(*asset, owner, user, *amount)?;
+		}
+
+		Ok(())
+	}
+
+	/// Check every leg of `legs`, paying an amount of an asset to an account, can be paid from
+	/// the reserve of `owner`, without moving anything.
+	///
+	/// Reserves are checked against the sum of the legs of each asset. New accounts are created
+	/// by a transfer, which requires the minimum balance of the asset, and `owner` must keep it.
+	/// A claimer whose account was reaped since its claim can only be paid the minimum balance or more.
+	fn ensure_payable(
+		owner: &T::AccountId,
+		legs: &[(T::AccountId, AssetIdOf<T>, BalanceOf<T>)],
+	) -> DispatchResult {
+		let mut totals: Vec<(AssetIdOf<T>, BalanceOf<T>)> = Vec::new();
+		for (user, asset, amount) in legs.iter() {
+			match totals.iter_mut().find(|(total_asset, _)| total_asset == asset) {
+				Some((_, total)) => *total = total.saturating_add(*amount),
+				None => totals.push((*asset, *amount)),
+			}
+
+			if T::Assets::free_balance(*asset, user).is_zero() {
+				let minimum = T::Assets::minimum_balance(*asset);
+				ensure!(*amount >= minimum, Error::<T>::BelowExistentialDeposit);
+				ensure!(T::Assets::free_balance(*asset, owner) >= minimum, Error::<T>::InsufficientBalance);
+			}
+		}
+
+		for (asset, total) in totals.iter() {
+			ensure!(T::Assets::reserved_balance(*asset, owner) >= *total, Error::<T>::InsufficientBalance);
+		}
+
+		Ok(())
+	}
+
+	/// Return all `reserves` of `owner` to its free balances.
+	fn unreserve_all(owner: &T::AccountId, reserves: &[(AssetIdOf<T>, BalanceOf<T>)]) {
+		for (asset, amount) in reserves.iter() {
+			T::Assets::unreserve(*asset, owner, *amount);
+		}
+	}
+
+	/// Move `amount` of the native deposit of `owner` to `user`, like `pay_from_reserve`.
+	fn pay_deposit(owner: &T::AccountId, user: &T::AccountId, amount: BalanceOf<T>) -> DispatchResult {
+		if T::Currency::free_balance(user).is_zero() {
+			T::Currency::unreserve(owner, amount);
+			T::Currency::transfer(owner, user, amount, ExistenceRequirement::KeepAlive).map_err(|e| {
+				let reserved = T::Currency::reserve(owner, amount);
+				debug_assert!(reserved.is_ok(), "amount was unreserved right before; qed");
+				e
+			})
+		} else {
+			T::Currency::repatriate_reserved(owner, user, amount).map(|_| ())
+		}
+	}
+
+	/// Schedule the automatic settlement of the RedPacket `id` after block `expires_at`.
+	fn schedule(expires_at: T::BlockNumber, id: T::PacketId) {
+		let position = Self::expiration_count(expires_at);
+		<Expirations<T>>::insert(&expires_at, &position, id);
+		<ExpirationCount<T>>::insert(expires_at, position + 1);
+	}
+
+	/// Settle RedPackets which expired before block `now`, within the per-block budget.
+	///
+	/// A RedPacket stays at the cursor until it is closed, so one with more claims than
+	/// the budget is paid over several blocks before the cursor moves on.
+	fn settle_expired(now: T::BlockNumber) {
+		let limit = T::MaxPayoutsPerBlock::get();
+		let mut budget = limit;
+		let (mut block, mut position) = Self::settlement_cursor();
+
+		// RedPackets expiring at `now - 1` can not be claimed from this block on.
+		while block < now && budget > 0 {
+			if position >= Self::expiration_count(block) {
+				<ExpirationCount<T>>::remove(block);
+				block += One::one();
+				position = 0;
+				budget -= 1;
+				continue;
+			}
+
+			// RedPackets closed early might already be reaped.
+			let entry = Self::expiration(&block, &position)
+				.and_then(|id| Self::packets(id).map(|packet| (id, packet)));
+			let (id, mut packet) = match entry {
+				Some((id, packet)) if packet.closed_at.is_none() => (id, packet),
+				// RedPackets distributed or refunded by the creator are already closed.
+				_ => {
+					<Expirations<T>>::remove(&block, &position);
+					position += 1;
+					budget -= 1;
+					continue;
+				},
+			};
+
+			if let Some(deadline) = packet.deadline.filter(|_| !Self::is_expired(&packet)) {
+				// Blocks came faster than expected, check again around the deadline.
+				packet.expires_at = now + Self::blocks_until(deadline);
+				Self::schedule(packet.expires_at, id);
+				<Packets<T>>::insert(id, packet);
+				<Expirations<T>>::remove(&block, &position);
+				position += 1;
+				budget -= 1;
+				continue;
+			}
+
+			// Every claim of the batch pays each asset of the bundle, closing counts as one more.
+			let assets = (packet.bundle.len() as u32).saturating_add(1);
+			let remaining = packet.claimed - Self::distribution_progress(id).0;
+			let mut claims = remaining.min(T::MaxPayoutsPerCall::get()).min((budget - 1) / assets);
+			if claims == 0 && remaining > 0 {
+				// A claim with a large bundle only fits in a whole budget.
+				if budget < limit {
+					break;
+				}
+				claims = 1;
+			}
+			budget = budget.saturating_sub(claims.saturating_mul(assets).saturating_add(1));
+
+			// RedPackets with claims left to pay stay at the cursor.
+			if Self::settle(id, packet, claims) {
+				<Expirations<T>>::remove(&block, &position);
+				position += 1;
+			}
+		}
+
+		<SettlementCursor<T>>::put((block, position));
+	}
+
+	/// Draw a random share for the next claim of a lucky RedPacket.
+	///
+	/// The share is taken from `[MinimumShare, 2 * unclaimed / remaining]`, capped so that every
+	/// remaining slot can still receive `MinimumShare`. The last claim takes all that is left.
+	fn lucky_share(packet: &PacketOf<T>, user: &T::AccountId) -> BalanceOf<T> {
+		let remaining = packet.count.saturating_sub(packet.claimed);
+		if remaining <= 1 {
+			return packet.unclaimed;
+		}
+
+		let minimum = T::MinimumShare::get();
+		let reserved_for_others = minimum.saturating_mul(<BalanceOf<T>>::from(remaining - 1));
+		let available = packet.unclaimed.saturating_sub(reserved_for_others);
+		let double_mean = (packet.unclaimed / <BalanceOf<T>>::from(remaining))
+			.saturating_mul(<BalanceOf<T>>::from(2u32));
+		let upper = available.min(double_mean);
+
+		if upper <= minimum {
+			return minimum;
+		}
+
+		let seed = T::Randomness::random(&(b"redpacket/lucky", packet.id, user, packet.claimed).encode());
+		let random = u64::decode(&mut seed.as_ref()).unwrap_or_default();
+		let span = upper - minimum + One::one();
+
+		minimum + <BalanceOf<T>>::unique_saturated_from(random) % span
+	}
+}
+
+/// Charge the transaction fee like `ChargeTransactionPayment`, except for free claims.
+///
+/// Encoded like `ChargeTransactionPayment`, its only field being the tip.
+#[derive(Encode, Decode, Clone, Eq, PartialEq)]
+pub struct ChargeClaimFees<T: Trait + transaction_payment::Trait + Send + Sync>(ChargeTransactionPayment<T>);
+
+impl<T: Trait + transaction_payment::Trait + Send + Sync> From<ChargeTransactionPayment<T>> for ChargeClaimFees<T> {
+	fn from(charge: ChargeTransactionPayment<T>) -> Self {
+		ChargeClaimFees(charge)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<T: Trait + transaction_payment::Trait + Send + Sync> sp_std::fmt::Debug for ChargeClaimFees<T> {
+	fn fmt(&self, f: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		write!(f, "ChargeClaimFees<{:?}>", self.0)
+	}
+}
+
+#[cfg(not(feature = "std"))]
+impl<T: Trait + transaction_payment::Trait + Send + Sync> sp_std::fmt::Debug for ChargeClaimFees<T> {
+	fn fmt(&self, _: &mut sp_std::fmt::Formatter) -> sp_std::fmt::Result {
+		Ok(())
+	}
+}
+
+impl<T: Trait + transaction_payment::Trait + Send + Sync> ChargeClaimFees<T> where
+	<T as system::Trait>::Call: IsSubType<Module<T>, T>,
+{
+	/// Whether `call` by `who` is a claim which would succeed, within the free claim limits.
+	///
+	/// Claims which fail would be free spam, so they pay like any other call.
+	/// `claim_with_proof` is never free, its proof is only checked on dispatch.
+	fn is_free_claim(who: &T::AccountId, call: &<T as system::Trait>::Call) -> bool {
+		let (id, signature) = match call.is_sub_type() {
+			Some(Call::claim(id)) => (*id, None),
+			Some(Call::claim_with_secret(id, signature)) => (*id, Some(signature)),
+			_ => return false,
+		};
+
+		if <Module<T>>::free_claims_in_block() >= T::MaxFreeClaimsPerBlock::get() {
+			return false;
+		}
+
+		let now = <system::Module<T>>::block_number();
+		if let Some(last) = <Module<T>>::last_free_claim(who) {
+			if now < last.saturating_add(T::FreeClaimInterval::get()) {
+				return false;
+			}
+		}
+
+		<Module<T>>::packets(id).map_or(false, |packet| {
+			<Module<T>>::check_claim(who, id, &packet, signature).is_ok()
+		})
+	}
+}
+
+impl<T: Trait + transaction_payment::Trait + Send + Sync> SignedExtension for ChargeClaimFees<T> where
+	<T as system::Trait>::Call: IsSubType<Module<T>, T>,
+{
+	type AccountId = T::AccountId;
+	type Call = <T as system::Trait>::Call;
+	type AdditionalSigned = ();
+	type DispatchInfo = DispatchInfo;
+	type Pre = ();
+
+	fn additional_signed(&self) -> Result<(), TransactionValidityError> {
+		Ok(())
+	}
+
+	fn validate(
+		&self,
+		who: &Self::AccountId,
+		call: &Self::Call,
+		info: Self::DispatchInfo,
+		len: usize,
+	) -> TransactionValidity {
+		if Self::is_free_claim(who, call) {
+			return Ok(ValidTransaction::default());
+		}
+		self.0.validate(who, call, info, len)
+	}
+
+	fn pre_dispatch(
+		self,
+		who: &Self::AccountId,
+		call: &Self::Call,
+		info: Self::DispatchInfo,
+		len: usize,
+	) -> Result<(), TransactionValidityError> {
+		if Self::is_free_claim(who, call) {
+			<FreeClaimsInBlock>::mutate(|count| *count += 1);
+			<LastFreeClaim<T>>::insert(who, <system::Module<T>>::block_number());
+			return Ok(());
+		}
+		self.0.pre_dispatch(who, call, info, len)
+	}
+}
+
+impl<T: Trait> ValidateUnsigned for Module<T> {
+	type Call = Call<T>;
+
+	fn validate_unsigned(call: &Self::Call) -> TransactionValidity {
+		let (id, claimer, signature) = match call {
+			Call::claim_unsigned(id, claimer, signature) => (*id, claimer, signature),
+			_ => return InvalidTransaction::Call.into(),
+		};
+
+		let message = (CLAIM_CONTEXT, id, claimer).encode();
+		if !signature.verify(&message[..], claimer) {
+			return InvalidTransaction::BadProof.into();
+		}
+
+		let packet = match Self::packets(id) {
+			Some(packet) => packet,
+			None => return InvalidTransaction::Call.into(),
+		};
+		let now = <system::Module<T>>::block_number();
+
+		// Claims which can never succeed again are dropped from the pool.
+		if Self::is_expired(&packet)
+			|| packet.closed_at.is_some()
+			|| packet.unclaimed.is_zero()
+			|| <ClaimOf<T>>::exists(&id, claimer)
+		{
+			return InvalidTransaction::Stale.into();
+		}
+
+		if !Self::is_open(&packet) {
+			return InvalidTransaction::Future.into();
+		}
+
+		if Self::check_claim(claimer, id, &packet, None).is_err() {
+			return InvalidTransaction::Call.into();
+		}
+
+		if Self::free_claims_in_block() >= T::MaxFreeClaimsPerBlock::get() {
+			return InvalidTransaction::ExhaustsResources.into();
+		}
+
+		let mut provides = Vec::new();
+		provides.push((id, claimer).encode());
+
+		Ok(ValidTransaction {
+			priority: T::UnsignedPriority::get(),
+			requires: Vec::new(),
+			provides: provides,
+			// The settlement of RedPackets with a deadline might be rescheduled, claims can be sent again.
+			longevity: packet.expires_at.saturating_sub(now).saturating_add(One::one()).unique_saturated_into(),
+			propagate: true,
+		})
+	}
+}
+
+/// Record the block number new accounts are created at, for the `min_age` eligibility rule.
+impl<T: Trait> OnNewAccount<T::AccountId> for Module<T> {
+	fn on_new_account(who: &T::AccountId) {
+		<AccountCreatedAt<T>>::insert(who, <system::Module<T>>::block_number());
+	}
+}
+
+/// Forget the eligibility records of reaped accounts, they start over as new accounts when created again.
+impl<T: Trait> OnFreeBalanceZero<T::AccountId> for Module<T> {
+	fn on_free_balance_zero(who: &T::AccountId) {
+		// The account still exists as long as it has a reserved balance.
+		if T::Currency::reserved_balance(who).is_zero() {
+			<AccountCreatedAt<T>>::remove(who);
+			<ClaimsInPeriod<T>>::remove(who);
+			<LastFreeClaim<T>>::remove(who);
+		}
+	}
+}
+
+sp_api::decl_runtime_apis! {
+	/// Queries of RedPackets for clients.
+	pub trait RedPacketApi<AccountId, PacketId, Balance, Packet> where
+		AccountId: Codec,
+		PacketId: Codec,
+		Balance: Codec,
+		Packet: Codec,
+	{
+		/// The RedPacket `id`, if it was created and not reaped yet.
+		fn packet(id: PacketId) -> Option<Packet>;
+
+		/// Amount `who` receives by claiming from the RedPacket `id` now, if it can claim at all.
+		fn claimable_amount(id: PacketId, who: AccountId) -> Option<Balance>;
+
+		/// Ids of the RedPackets created by `owner`.
+		fn packets_by_owner(owner: AccountId) -> Vec<PacketId>;
+
+		/// Whether the owner of the RedPacket `id` can distribute it now.
+		fn is_distributable(id: PacketId) -> bool;
+	}
+}
+
+decl_event!(
+	pub enum Event<T> 
+		where 
+			AccountId = <T as system::Trait>::AccountId,
+			PacketId = <T as Trait>::PacketId,
+			AssetId = AssetIdOf<T>,
+			Balance = BalanceOf<T>
+	{
+		/// A new RedPacket was created, holding an asset.
+		Created(PacketId, AccountId, AssetId, Balance, u32),
+
+		/// A new claim was created.
+		Claimed(PacketId, AccountId, Balance),
+
+		/// Distribute the RedPacket to claimers.
+		Distributed(PacketId, AccountId, Balance),
+
+		/// A batch of claims was paid, with the number of claims left to pay.
+		PartiallyDistributed(PacketId, AccountId, Balance, u32),
+
+		/// The unclaimed balance of a RedPacket was returned to its creator.
+		Refunded(PacketId, AccountId, Balance),
+
+		/// A claim which could not be paid was returned to the creator of the RedPacket.
+		Forfeited(PacketId, AccountId, Balance),
+
+		/// A closed RedPacket and its claims were removed from storage by an account.
+		Reaped(PacketId, AccountId),
+
+		/// A RedPacket nobody claimed from was withdrawn by its creator, with the returned balance.
+		Cancelled(PacketId, AccountId, Balance),
+	}
+);
+
+decl_error! {
+	/// Error
+	pub enum Error for Module<T: Trait> {
+		/// Sender's balance is too low.
+		InsufficientBalance,
+		/// Parameter must be greater than zero
+		GreaterThanZero,
+		/// No RedPacket with the given id
+		PacketNotFound,
+		/// RedPacket was Expired
+		Expired,
 		/// Aleadly claimed by a Account
 		AlreadyClaimed,
 		/// Not owner
@@ -249,224 +1958,2026 @@ decl_error! {
 		AlreadyDistributed,
 		/// Unavailable
 		Unavailable,
+		/// Total is too small to give every participant the minimum share
+		BelowMinimumShare,
+		/// RedPacket is not expired yet
+		NotExpired,
+		/// RedPacket was already closed
+		AlreadyClosed,
+		/// RedPacket is not closed yet
+		NotClosed,
+		/// RedPacket is closed but still in its retention period
+		StillRetained,
+		/// Account is not a recipient of the RedPacket
+		NotEligible,
+		/// Call does not match the kind of the RedPacket
+		UnsupportedKind,
+		/// Merkle proof does not match the root of the RedPacket
+		InvalidProof,
+		/// Missing or wrong signature of the secret code of the RedPacket
+		InvalidSecret,
+		/// The claimer did not sign the unsigned claim
+		InvalidSignature,
+		/// An asset appears twice in the bundle of the RedPacket
+		DuplicateAsset,
+		/// The bundle of the RedPacket has more than `MaxBundleSize` assets
+		BundleTooLarge,
+		/// The deadline of the RedPacket is not in the future
+		DeadlinePassed,
+		/// The RedPacket does not accept claims yet
+		NotYetOpen,
+		/// The RedPacket opens after it expires
+		InvalidOpening,
+		/// Free balance of the claimer is below the minimum of the RedPacket
+		BalanceTooLow,
+		/// Account of the claimer is younger than the minimum age of the RedPacket
+		AccountTooNew,
+		/// The claimer reached the maximum number of claims per period of the RedPacket
+		TooManyClaims,
+		/// The creator of the RedPacket can not claim from it
+		OwnerCannotClaim,
+		/// A payout would create an account with less than the existential deposit
+		BelowExistentialDeposit,
+		/// The block reached the maximum number of free claims
+		FreeClaimsExhausted,
+
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::assets;
+	use balances::GenesisConfig;
+	use frame_support::{
+		impl_outer_origin, impl_outer_event, assert_ok, assert_noop, parameter_types,
+		weights::{Weight, GetDispatchInfo},
+	};
+	use sp_core::H256;
+	use std::cell::RefCell;
+	// The testing primitives are very useful for avoiding having to work with signatures
+	// or public keys. `u64` is used as the `AccountId` and no `Signature`s are required.
+	use sp_runtime::{
+		Perbill,
+		traits::{BlakeTwo256, IdentityLookup, OnInitialize, OnFinalize, OnRuntimeUpgrade},
+		testing::{Header, TestSignature, UintAuthorityId},
+	};
+
+	type Hashing = BlakeTwo256;
+
+	impl_outer_origin! {
+		pub enum Origin for Test  {}
+	}
+
+	mod redpacket {
+		pub use crate::redpacket::Event;
+	}
+
+	impl_outer_event! {
+		pub enum TestEvent for Test {
+			balances<T>,
+			assets<T>,
+			redpacket<T>,
+		}
+	}
+
+	// For testing the module, we construct most of a mock runtime. This means
+	// first constructing a configuration type (`Test`) which `impl`s each of the
+	// configuration traits of modules we want to use.
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	parameter_types! {
+		pub const BlockHashCount: u64 = 250;
+		pub const MaximumBlockWeight: Weight = 1024;
+		pub const MaximumBlockLength: u32 = 2 * 1024;
+		pub const AvailableBlockRatio: Perbill = Perbill::one();
+	}
+	impl system::Trait for Test {
+		type Origin = Origin;
+		type Index = u64;
+		type Call = Call<Test>;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = TestEvent;
+		type BlockHashCount = BlockHashCount;
+		type MaximumBlockWeight = MaximumBlockWeight;
+		type AvailableBlockRatio = AvailableBlockRatio;
+		type MaximumBlockLength = MaximumBlockLength;
+		type Version = ();
+		type ModuleToIndex = ();
+	}
+
+	parameter_types! {
+		pub const TransferFee: u64 = 0;
+		pub const CreationFee: u64 = 0;
+	}
+	thread_local! {
+		static EXISTENTIAL_DEPOSIT: RefCell<u64> = RefCell::new(0);
+	}
+	pub struct ExistentialDeposit;
+	impl Get<u64> for ExistentialDeposit {
+		fn get() -> u64 { EXISTENTIAL_DEPOSIT.with(|v| *v.borrow()) }
+	}
+	fn set_existential_deposit(amount: u64) {
+		EXISTENTIAL_DEPOSIT.with(|v| *v.borrow_mut() = amount);
+	}
+	impl balances::Trait for Test {
+		type Balance = u64;
+		type OnFreeBalanceZero = RedPackets;
+		type OnNewAccount = RedPackets;
+		type Event = TestEvent;
+		type TransferPayment = ();
+		type DustRemoval = ();
+		type ExistentialDeposit = ExistentialDeposit;
+		type TransferFee = TransferFee;
+		type CreationFee = CreationFee;
+	}
+	impl randomness_collective_flip::Trait for Test {}
+	parameter_types! {
+		pub const MinimumPeriod: u64 = 1;
+	}
+	impl timestamp::Trait for Test {
+		type Moment = u64;
+		type OnTimestampSet = ();
+		type MinimumPeriod = MinimumPeriod;
+	}
+	parameter_types! {
+		// Every paid call costs exactly the base fee.
+		pub const TransactionBaseFee: u64 = 1;
+		pub const TransactionByteFee: u64 = 0;
+	}
+	impl transaction_payment::Trait for Test {
+		type Currency = balances::Module<Self>;
+		type OnTransactionPayment = ();
+		type TransactionBaseFee = TransactionBaseFee;
+		type TransactionByteFee = TransactionByteFee;
+		type WeightToFee = ();
+		type FeeMultiplierUpdate = ();
+	}
+	impl assets::Trait for Test {
+		type Event = TestEvent;
+		type Currency = balances::Module<Self>;
+		type AssetId = u32;
+	}
+	thread_local! {
+		static DEPOSIT_BASE: RefCell<u64> = RefCell::new(0);
+		static DEPOSIT_PER_SLOT: RefCell<u64> = RefCell::new(0);
+	}
+	pub struct PacketDepositBase;
+	impl Get<u64> for PacketDepositBase {
+		fn get() -> u64 { DEPOSIT_BASE.with(|v| *v.borrow()) }
+	}
+	pub struct PacketDepositPerSlot;
+	impl Get<u64> for PacketDepositPerSlot {
+		fn get() -> u64 { DEPOSIT_PER_SLOT.with(|v| *v.borrow()) }
+	}
+	fn set_deposit(base: u64, per_slot: u64) {
+		DEPOSIT_BASE.with(|v| *v.borrow_mut() = base);
+		DEPOSIT_PER_SLOT.with(|v| *v.borrow_mut() = per_slot);
+	}
+	parameter_types! {
+		pub const MinimumShare: u64 = 1;
+		pub const MaxPayoutsPerBlock: u32 = 4;
+		pub const MaxPayoutsPerCall: u32 = 2;
+		pub const MaxBundleSize: u32 = 2;
+		pub const FreeClaimInterval: u64 = 5;
+		pub const MaxFreeClaimsPerBlock: u32 = 2;
+		pub const UnsignedPriority: u64 = 100;
+		pub const ExpectedBlockTime: u64 = 6;
+		pub const ClaimPeriod: u64 = 10;
+		pub const RetentionPeriod: u64 = 10;
+		pub const ReapReward: u64 = 2;
+	}
+	impl Trait for Test {
+		type Currency = balances::Module<Self>;
+		type Assets = assets::Module<Self>;
+		type Event = TestEvent;
+		type PacketId = u32;
+		type Randomness = randomness_collective_flip::Module<Test>;
+		type MinimumShare = MinimumShare;
+		type MaxPayoutsPerBlock = MaxPayoutsPerBlock;
+		type MaxPayoutsPerCall = MaxPayoutsPerCall;
+		type MaxBundleSize = MaxBundleSize;
+		type PacketDepositBase = PacketDepositBase;
+		type PacketDepositPerSlot = PacketDepositPerSlot;
+		type RetentionPeriod = RetentionPeriod;
+		type ReapReward = ReapReward;
+		type Signature = TestSignature;
+		type Signer = UintAuthorityId;
+		type FreeClaimInterval = FreeClaimInterval;
+		type MaxFreeClaimsPerBlock = MaxFreeClaimsPerBlock;
+		type UnsignedPriority = UnsignedPriority;
+		type Time = timestamp::Module<Test>;
+		type ExpectedBlockTime = ExpectedBlockTime;
+		type ClaimPeriod = ClaimPeriod;
+	}
+	type RedPackets = Module<Test>;
+	type Timestamp = timestamp::Module<Test>;
+	type Assets = assets::Module<Test>;
+
+	// This function basically just builds a genesis storage key/value store according to
+	// our desired mockup.
+	fn new_test_ext() -> sp_io::TestExternalities {
+		// system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+		let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+		GenesisConfig::<Test> {
+			balances: vec![
+				(1, 100),
+				(2, 200),
+				(3, 300),
+				(4, 400),
+				(5, 1),
+			],
+			vesting: vec![]
+		}.assimilate_storage(&mut t).unwrap();
+		t.into()
+	}
+
+	fn immediate() -> PacketOptions<u64, u32, u64, u64, u64> {
+		PacketOptions { payout: PayoutMode::Immediate, ..Default::default() }
+	}
+
+	/// Run the signed extension checks of `call` by `who` before dispatching it.
+	fn pre_dispatch_claim(who: u64, call: Call<Test>) -> Result<(), TransactionValidityError> {
+		let info = call.get_dispatch_info();
+		ChargeClaimFees::<Test>::from(transaction_payment::ChargeTransactionPayment::from(0))
+			.pre_dispatch(&who, &call, info, 0)
+	}
+
+	/// Sign an unsigned claim with the key of `user`.
+	fn sign_claim(id: u32, user: u64) -> TestSignature {
+		TestSignature(user, (CLAIM_CONTEXT, id, user).encode())
+	}
+
+	/// Sign a claim of `user` with the keypair `key` of a secret code.
+	fn sign_secret(key: u64, id: u32, user: u64) -> TestSignature {
+		TestSignature(key, (SECRET_CONTEXT, id, user).encode())
+	}
+
+	/// Check `PacketsByOwner` and `ClaimsByAccount` agree with the RedPackets and claims in storage.
+	fn assert_indexes_consistent() {
+		for id in 0..RedPackets::next_packet_id() {
+			if let Some(packet) = RedPackets::packet(id) {
+				assert_eq!(RedPackets::packet_of_owner(&packet.owner, &id), Some(id));
+				for (user, _) in RedPackets::claims(id) {
+					assert_eq!(RedPackets::claimed_packet(&user, &id), Some(id));
+				}
+			}
+		}
+		for account in 0..20 {
+			for id in RedPackets::packets_by_owner(&account) {
+				assert_eq!(RedPackets::packet(id).map(|packet| packet.owner), Some(account));
+			}
+			for id in RedPackets::packets_claimed_by(&account) {
+				assert!(RedPackets::claim_of(&id, &account).is_some());
+			}
+		}
+	}
+
+	/// Build a Merkle tree of `(account, amount)` leaves, returns the root and the proof of every leaf.
+	fn merkle_tree(leaves: &[(u64, u64)]) -> (H256, Vec<Vec<H256>>) {
+		let mut level = leaves.iter().map(|leaf| Hashing::hash_of(leaf)).collect::<Vec<_>>();
+		level.resize(level.len().next_power_of_two(), H256::default());
+		let mut proofs = vec![Vec::new(); leaves.len()];
+		let mut positions = (0..leaves.len()).collect::<Vec<_>>();
+		while level.len() > 1 {
+			for (proof, position) in proofs.iter_mut().zip(positions.iter_mut()) {
+				proof.push(level[*position ^ 1]);
+				*position /= 2;
+			}
+			level = level.chunks(2).map(|pair| Hashing::hash_of(&(pair[0], pair[1]))).collect();
+		}
+		(level[0], proofs)
+	}
+
+	/// Ids of the RedPackets still scheduled in `Expirations` at `block`.
+	fn expirations(block: u64) -> Vec<u32> {
+		(0..RedPackets::expiration_count(block))
+			.filter_map(|position| RedPackets::expiration(&block, &position))
+			.collect()
+	}
+
+	/// Events of this module deposited so far.
+	fn redpacket_events() -> Vec<RawEvent<u64, u32, u32, u64>> {
+		system::Module::<Test>::events()
+			.into_iter()
+			.filter_map(|record| match record.event {
+				TestEvent::redpacket(event) => Some(event),
+				_ => None,
+			})
+			.collect()
+	}
+
+	fn run_to_block(n: u64) {
+		while system::Module::<Test>::block_number() < n {
+			RedPackets::on_finalize(system::Module::<Test>::block_number());
+			let next = system::Module::<Test>::block_number() + 1;
+			system::Module::<Test>::set_block_number(next);
+			RedPackets::on_initialize(next);
+		}
+	}
+
+	/// Like `run_to_block`, the timestamp of every block is `block_time` after the previous one.
+	/// `on_initialize` sees the timestamp of the previous block, as the timestamp inherent comes later.
+	fn run_to_block_with_time(n: u64, block_time: u64) {
+		while system::Module::<Test>::block_number() < n {
+			RedPackets::on_finalize(system::Module::<Test>::block_number());
+			let next = system::Module::<Test>::block_number() + 1;
+			system::Module::<Test>::set_block_number(next);
+			RedPackets::on_initialize(next);
+			Timestamp::set_timestamp(Timestamp::now() + block_time);
+		}
+	}
+
+
+	#[test]
+	fn create_redpacket_should_work() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 5, 100, Default::default()));
+		});
+	}
+
+	#[test]
+	fn create_redpacket_should_fail_if_insufficient_balance() {
+		new_test_ext().execute_with(|| {
+			assert_noop!(RedPackets::create(Origin::signed(5), 1, 5, 100, Default::default()), Error::<Test>::InsufficientBalance);
+		});
+	}
+
+	#[test]
+	fn create_redpacket_should_failed_with_invalid_arguments() {
+		new_test_ext().execute_with(|| {
+			assert_noop!(RedPackets::create(Origin::signed(1), 0, 5, 100, Default::default()), Error::<Test>::GreaterThanZero);
+			assert_noop!(RedPackets::create(Origin::signed(1), 1, 0, 100, Default::default()), Error::<Test>::GreaterThanZero);
+			assert_noop!(RedPackets::create(Origin::signed(1), 1, 5, 0, Default::default()), Error::<Test>::GreaterThanZero);
+		});
+	}
+
+	#[test]
+	fn claim_should_work() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 5, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			assert_ok!(RedPackets::claim(Origin::signed(2), id));
+			assert_ok!(RedPackets::claim(Origin::signed(3), id));
+		});
+	}
+
+	#[test]
+	fn claim_should_fail_if_expired() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(1), 1, 5, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			system::Module::<Test>::set_block_number(102);
+			assert_noop!(RedPackets::claim(Origin::signed(2), id), Error::<Test>::Expired);			
+		});
+	}
+
+	#[test]
+	fn claim_should_fail_if_unavailable(){
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+			assert_noop!(RedPackets::claim(Origin::signed(4), id), Error::<Test>::Unavailable);
+		});
+	}
+
+	#[test]
+	fn claim_should_fail_if_already_claimed() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			assert_noop!(RedPackets::claim(Origin::signed(2), id), Error::<Test>::AlreadyClaimed);
+		});
+	}
+
+	#[test]
+	fn distribute_should_work(){
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 2);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 1);
+			assert_eq!(balances::Module::<Test>::free_balance(&3), 300 + 1);
+		});
+	}
+
+	#[test]
+	fn distribute_should_fail_if_already_distributed(){
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+			assert_noop!(RedPackets::distribute(Origin::signed(1), id), Error::<Test>::AlreadyDistributed);
+		});
+	}
+
+	#[test]
+	fn distribute_should_fail_if_not_owner() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+			assert_noop!(RedPackets::distribute(Origin::signed(4), id), Error::<Test>::NotOwner);
+		});
+	}
+
+	#[test]
+	fn distribute_should_fail_if_not_expired_and_with_remaining_amount() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			assert_noop!(RedPackets::distribute(Origin::signed(1), id), Error::<Test>::CanNotBeDistributed);
+		});
+	}
+
+	#[test]
+	fn distribute_should_work_if_not_expired_and_no_remaining_amount() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+			system::Module::<Test>::set_block_number(102);
+
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+		});
+	}
+
+	#[test]
+	fn create_lucky_should_fail_if_below_minimum_share() {
+		new_test_ext().execute_with(|| {
+			assert_noop!(RedPackets::create_lucky(Origin::signed(1), 4, 5, 100, Default::default()), Error::<Test>::BelowMinimumShare);
+			assert_ok!(RedPackets::create_lucky(Origin::signed(1), 5, 5, 100, Default::default()));
+		});
+	}
+
+	#[test]
+	fn lucky_shares_should_sum_to_total() {
+		new_test_ext().execute_with(|| {
+			for &(total, count) in &[(10, 10), (50, 7), (99, 3), (200, 20), (1, 1)] {
+				assert_ok!(RedPackets::create_lucky(Origin::signed(4), total, count, 100, Default::default()));
+				let id = RedPackets::next_packet_id() - 1;
+				for user in 0..count {
+					assert_ok!(RedPackets::claim(Origin::signed(100 + user as u64), id));
+				}
+				assert_noop!(RedPackets::claim(Origin::signed(99), id), Error::<Test>::Unavailable);
+
+				let claims = RedPackets::claims(id);
+				assert_eq!(claims.len(), count as usize);
+				assert!(claims.iter().all(|(_, amount)| *amount >= MinimumShare::get()));
+				assert_eq!(claims.iter().map(|(_, amount)| amount).sum::<u64>(), total);
+			}
+		});
+	}
+
+	#[test]
+	fn distribute_lucky_redpacket_should_work() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create_lucky(Origin::signed(1), 10, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+
+			let claims = RedPackets::claims(id);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + claims[0].1);
+			assert_eq!(balances::Module::<Test>::free_balance(&3), 300 + claims[1].1);
+		});
+	}
+
+	#[test]
+	fn claim_should_pay_at_once_if_immediate() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, immediate()));
+			let id = RedPackets::next_packet_id() - 1;
+			assert_ok!(RedPackets::claim(Origin::signed(2), id));
+
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 1);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 1);
+
+			assert_ok!(RedPackets::claim(Origin::signed(3), id));
+			assert_eq!(balances::Module::<Test>::free_balance(&3), 300 + 1);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 2);
+		});
+	}
+
+	#[test]
+	fn failed_payout_to_new_account_should_keep_reserve() {
+		new_test_ext().execute_with(|| {
+			set_existential_deposit(10);
+			assert_ok!(RedPackets::create(Origin::signed(1), 5, 2, 100, immediate()));
+			let id = RedPackets::next_packet_id() - 1;
+
+			// A quota below the existential deposit can not create account 7.
+			assert!(RedPackets::claim(Origin::signed(7), id).is_err());
+			assert_eq!(balances::Module::<Test>::free_balance(&7), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 10);
+			assert_eq!(RedPackets::packet(id).unwrap().unclaimed, 10);
+
+			// Existing accounts are paid out of the reserve as usual.
+			assert_ok!(RedPackets::claim(Origin::signed(2), id));
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 5);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 5);
+		});
+	}
+
+	#[test]
+	fn settlement_should_return_claims_which_can_not_be_paid() {
+		new_test_ext().execute_with(|| {
+			set_existential_deposit(10);
+			run_to_block(1);
+			assert_ok!(RedPackets::create(Origin::signed(1), 5, 2, 10, Default::default()));
+			// A new account could not be paid a quota below the existential deposit.
+			assert_noop!(RedPackets::claim(Origin::signed(7), 0), Error::<Test>::BelowExistentialDeposit);
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+
+			// The account of a claimer is reaped before the settlement, the other claimer is paid all the same.
+			assert_ok!(balances::Module::<Test>::transfer(Origin::signed(2), 4, 200));
+			run_to_block(12);
+			let packet = RedPackets::packet(0).unwrap();
+			assert!(packet.distributed);
+			assert_eq!(packet.closed_at, Some(12));
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&3), 300 + 5);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 5);
+
+			let events = redpacket_events();
+			assert_eq!(
+				&events[events.len() - 2..],
+				&[RawEvent::Forfeited(0, 2, 5), RawEvent::Distributed(0, 1, 5)][..]
+			);
+		});
+	}
+
+	#[test]
+	fn distribute_immediate_redpacket_should_only_release_unclaimed() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, immediate()));
+			let id = RedPackets::next_packet_id() - 1;
+			assert_ok!(RedPackets::claim(Origin::signed(2), id));
+			system::Module::<Test>::set_block_number(102);
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+		});
+	}
+
+	#[test]
+	fn distribute_should_refund_unclaimed_balance() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			system::Module::<Test>::set_block_number(102);
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(RedPackets::packets(id).unwrap().closed_at, Some(102));
+			let events = redpacket_events();
+			assert_eq!(
+				&events[events.len() - 2..],
+				&[RawEvent::Distributed(id, 1, 10), RawEvent::Refunded(id, 1, 20)][..]
+			);
+			assert_noop!(RedPackets::refund(Origin::signed(1), id), Error::<Test>::AlreadyClosed);
+		});
+	}
+
+	#[test]
+	fn refund_should_work() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			system::Module::<Test>::set_block_number(102);
+			assert_ok!(RedPackets::refund(Origin::signed(1), id));
+
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(redpacket_events(), vec![
+				RawEvent::Created(id, 1, 0, 30, 3),
+				RawEvent::Claimed(id, 2, 10),
+				RawEvent::Distributed(id, 1, 10),
+				RawEvent::Refunded(id, 1, 20),
+			]);
+			assert_noop!(RedPackets::refund(Origin::signed(1), id), Error::<Test>::AlreadyClosed);
+			assert_noop!(RedPackets::distribute(Origin::signed(1), id), Error::<Test>::AlreadyDistributed);
+		});
+	}
+
+	#[test]
+	fn refund_should_fail_if_not_expired_or_not_owner() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			assert_noop!(RedPackets::refund(Origin::signed(1), id), Error::<Test>::NotExpired);
+			system::Module::<Test>::set_block_number(102);
+			assert_noop!(RedPackets::refund(Origin::signed(2), id), Error::<Test>::NotOwner);
+		});
+	}
+
+	#[test]
+	fn claim_should_fail_if_closed() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 1, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+			assert_noop!(RedPackets::claim(Origin::signed(3), id), Error::<Test>::AlreadyClosed);
+		});
+	}
+
+	#[test]
+	fn expired_redpacket_should_be_settled_automatically() {
+		new_test_ext().execute_with(|| {
+			run_to_block(1);
+			RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			assert_eq!(expirations(101), vec![id]);
+
+			run_to_block(101);
+			assert_eq!(RedPackets::packets(id).unwrap().closed_at, None);
+
+			run_to_block(102);
+			assert_eq!(RedPackets::packets(id).unwrap().closed_at, Some(102));
+			assert_eq!(expirations(101), vec![]);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+		});
+	}
+
+	#[test]
+	fn automatic_settlement_should_carry_over_to_next_block() {
+		new_test_ext().execute_with(|| {
+			run_to_block(1);
+			for _ in 0..5 {
+				RedPackets::create(Origin::signed(1), 1, 3, 100, Default::default()).ok();
+			}
+
+			// Closing a RedPacket without claims counts as one payout.
+			run_to_block(102);
+			for id in 0..4 {
+				assert_eq!(RedPackets::packets(id).unwrap().closed_at, Some(102));
+			}
+			assert_eq!(RedPackets::packets(4).unwrap().closed_at, None);
+			assert_eq!(RedPackets::settlement_cursor(), (101, 4));
+			assert_eq!(expirations(101), vec![4]);
+
+			run_to_block(103);
+			assert_eq!(RedPackets::packets(4).unwrap().closed_at, Some(103));
+			assert!(expirations(101).is_empty());
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100);
+		});
+	}
+
+	#[test]
+	fn automatic_settlement_should_skip_closed_redpackets() {
+		new_test_ext().execute_with(|| {
+			run_to_block(1);
+			RedPackets::create(Origin::signed(1), 10, 1, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+
+			run_to_block(102);
+			assert_eq!(RedPackets::packets(id).unwrap().closed_at, Some(1));
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+		});
+	}
+
+	#[test]
+	fn distribute_should_be_paged() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(4), 10, 5, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			for user in 10..15 {
+				RedPackets::claim(Origin::signed(user), id).ok();
+			}
+
+			assert_ok!(RedPackets::distribute(Origin::signed(4), id));
+			assert_eq!(RedPackets::distribution_progress(id), (2, 20));
+			assert!(!RedPackets::packets(id).unwrap().distributed);
+			assert_eq!(balances::Module::<Test>::free_balance(&11), 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&12), 0);
+			assert_eq!(redpacket_events().last(), Some(&RawEvent::PartiallyDistributed(id, 4, 20, 3)));
+
+			assert_ok!(RedPackets::distribute(Origin::signed(4), id));
+			assert_eq!(RedPackets::distribution_progress(id), (4, 40));
+			assert!(!RedPackets::packets(id).unwrap().distributed);
+			assert_eq!(redpacket_events().last(), Some(&RawEvent::PartiallyDistributed(id, 4, 20, 1)));
+
+			assert_ok!(RedPackets::distribute(Origin::signed(4), id));
+			assert_eq!(RedPackets::distribution_progress(id), (0, 0));
+			assert!(RedPackets::packets(id).unwrap().distributed);
+			// Every slot was claimed, nothing is refunded.
+			assert_eq!(redpacket_events().last(), Some(&RawEvent::Distributed(id, 4, 50)));
+			assert_noop!(RedPackets::distribute(Origin::signed(4), id), Error::<Test>::AlreadyDistributed);
+
+			for user in 10..15 {
+				assert_eq!(balances::Module::<Test>::free_balance(&user), 10);
+			}
+			assert_eq!(balances::Module::<Test>::free_balance(&4), 400 - 50);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&4), 0);
+		});
+	}
+
+	#[test]
+	fn automatic_settlement_should_be_paged() {
+		new_test_ext().execute_with(|| {
+			run_to_block(1);
+			RedPackets::create(Origin::signed(4), 10, 5, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			for user in 10..13 {
+				RedPackets::claim(Origin::signed(user), id).ok();
+			}
+
+			// Two claims and the next one does not fit with closing.
+			run_to_block(102);
+			assert_eq!(RedPackets::distribution_progress(id), (2, 20));
+			assert_eq!(RedPackets::settlement_cursor(), (101, 0));
+
+			run_to_block(103);
+			assert_eq!(RedPackets::packets(id).unwrap().closed_at, Some(103));
+			assert!(expirations(101).is_empty());
+			assert_eq!(balances::Module::<Test>::free_balance(&12), 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&4), 400 - 30);
+		});
+	}
+
+	#[test]
+	fn claim_should_record_claim_info() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(5);
+			RedPackets::create(Origin::signed(1), 3, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			assert_ok!(RedPackets::claim(Origin::signed(2), id));
+
+			assert_eq!(RedPackets::claim_of(&id, &2), Some(ClaimInfo { amount: 3, block: 5 }));
+			assert_eq!(RedPackets::claim_of(&id, &3), None);
+			assert_eq!(RedPackets::claimer(&id, &0), 2);
+			assert_eq!(RedPackets::packets(id).unwrap().claimed, 1);
+		});
+	}
+
+	#[test]
+	fn runtime_upgrade_should_migrate_claims() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(5);
+			// RedPackets and claims in the layout before this module was extended.
+			let legacy = [(0, 2, 0, 100, 1, false), (1, 4, 2, 3, 1, false), (2, 6, 3, 4, 2, true)];
+			for &(id, total, unclaimed, expires_at, owner, distributed) in legacy.iter() {
+				let packet: LegacyPacketOf<Test> =
+					LegacyPacket { id, total, unclaimed, count: 2, expires_at, owner, distributed };
+				storage::unhashed::put(&<Packets<Test>>::hashed_key_for(id), &packet);
+			}
+			<Claims<Test>>::insert(0, vec![2, 3]);
+			<Claims<Test>>::insert(1, vec![4]);
+			<Claims<Test>>::insert(2, vec![3]);
+			<NextPacketId<Test>>::put(3);
+			assert_ok!(<balances::Module<Test> as ReservableCurrency<u64>>::reserve(&1, 6));
+
+			RedPackets::on_runtime_upgrade();
+
+			assert_eq!(RedPackets::claims(0), vec![(2, 1), (3, 1)]);
+			assert_eq!(RedPackets::claim_of(&1, &4), Some(ClaimInfo { amount: 2, block: 0 }));
+			assert_eq!(RedPackets::claimer(&1, &0), 4);
+			assert!(!<Claims<Test>>::exists(0));
+			assert!(!<Claims<Test>>::exists(1));
+
+			let packet = RedPackets::packet(1).unwrap();
+			assert_eq!((packet.kind, packet.payout, packet.asset), (PacketKind::Average, PayoutMode::Deferred, 0));
+			assert_eq!((packet.total, packet.unclaimed, packet.count, packet.claimed), (4, 2, 2, 1));
+			assert_eq!(packet.closed_at, None);
+			let packet = RedPackets::packet(2).unwrap();
+			assert!(packet.distributed);
+			assert_eq!((packet.claimed, packet.closed_at), (1, Some(5)));
+			assert_eq!(RedPackets::packets_claimed_by(&3), vec![0, 2]);
+			assert_indexes_consistent();
+
+			// The expired RedPacket is settled in the next block, the other one once it expires.
+			assert_eq!(expirations(5), vec![1]);
+			assert_eq!(expirations(100), vec![0]);
+			run_to_block(6);
+			assert_eq!(RedPackets::packet(1).unwrap().closed_at, Some(6));
+			assert_eq!(balances::Module::<Test>::free_balance(&4), 400 + 2);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 2);
+
+			// A second upgrade leaves the migrated claims alone.
+			<Claims<Test>>::insert(1, vec![5]);
+			RedPackets::on_runtime_upgrade();
+			assert_eq!(RedPackets::claim_of(&1, &5), None);
+		});
+	}
+
+	#[test]
+	fn settle_weight_should_scale_with_count() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(1), 1, 1, 100, Default::default()).ok();
+			RedPackets::create(Origin::signed(1), 1, 50, 100, Default::default()).ok();
+
+			assert_eq!(Call::<Test>::distribute(0).get_dispatch_info().weight, BASE_WEIGHT + PAYOUT_WEIGHT);
+			assert_eq!(Call::<Test>::refund(0).get_dispatch_info().weight, BASE_WEIGHT + PAYOUT_WEIGHT);
+			// Capped by `MaxPayoutsPerCall`, the largest batch a single call can pay.
+			assert_eq!(Call::<Test>::distribute(1).get_dispatch_info().weight, BASE_WEIGHT + 2 * PAYOUT_WEIGHT);
+			assert_eq!(Call::<Test>::claim(1).get_dispatch_info().weight, BASE_WEIGHT + PAYOUT_WEIGHT);
+
+			let options = PacketOptions { recipients: vec![2, 3], ..Default::default() };
+			assert_eq!(
+				Call::<Test>::create(1, 2, 100, options).get_dispatch_info().weight,
+				BASE_WEIGHT + 2 * RECIPIENT_WEIGHT
+			);
+		});
+	}
+
+	#[test]
+	fn claim_weight_should_scale_with_bundle() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			let options = PacketOptions { bundle: vec![(1, 10), (2, 10)], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, options));
+
+			let weight = BASE_WEIGHT + 3 * PAYOUT_WEIGHT;
+			assert_eq!(Call::<Test>::claim(0).get_dispatch_info().weight, weight);
+			assert_eq!(Call::<Test>::claim_with_secret(0, sign_secret(42, 0, 2)).get_dispatch_info().weight, weight);
+			assert_eq!(Call::<Test>::claim_unsigned(0, 2, sign_claim(0, 2)).get_dispatch_info().weight, weight);
+			assert_eq!(Call::<Test>::claim(1).get_dispatch_info().weight, BASE_WEIGHT);
+		});
+	}
+
+	#[test]
+	fn create_should_reserve_deposit() {
+		new_test_ext().execute_with(|| {
+			set_deposit(5, 1);
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 5, 100, Default::default()));
+			assert_eq!(RedPackets::packets(0).unwrap().deposit, 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 5 + 10);
+
+			// Balance for the RedPacket alone is not enough.
+			assert_noop!(
+				RedPackets::create(Origin::signed(1), 10, 8, 100, Default::default()),
+				Error::<Test>::InsufficientBalance
+			);
+		});
+	}
+
+	#[test]
+	fn reap_should_return_deposit() {
+		new_test_ext().execute_with(|| {
+			set_deposit(5, 1);
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+
+			// The deposit stays reserved until the RedPacket is reaped.
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 7);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 2 - 7);
+
+			system::Module::<Test>::set_block_number(11);
+			assert_ok!(RedPackets::reap(Origin::signed(4), id));
+
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 2 - 2);
+			assert_eq!(balances::Module::<Test>::free_balance(&4), 400 + 2);
+			assert_eq!(redpacket_events().last(), Some(&RawEvent::Reaped(id, 4)));
+		});
+	}
+
+	#[test]
+	fn reap_should_remove_redpacket_and_claims() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			RedPackets::create(Origin::signed(1), 1, 2, 100, Default::default()).ok();
+			let id = RedPackets::next_packet_id() - 1;
+			RedPackets::claim(Origin::signed(2), id).ok();
+			RedPackets::claim(Origin::signed(3), id).ok();
+
+			assert_noop!(RedPackets::reap(Origin::signed(4), id), Error::<Test>::NotClosed);
+			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+			system::Module::<Test>::set_block_number(10);
+			assert_noop!(RedPackets::reap(Origin::signed(4), id), Error::<Test>::StillRetained);
+
+			system::Module::<Test>::set_block_number(11);
+			assert_ok!(RedPackets::reap(Origin::signed(4), id));
+
+			assert!(!<Packets<Test>>::exists(id));
+			assert_eq!(RedPackets::claim_of(&id, &2), None);
+			assert!(!<Claimers<Test>>::exists(&id, &0));
+			assert_eq!(redpacket_events().last(), Some(&RawEvent::Reaped(id, 4)));
+
+			// The expiry of a reaped RedPacket is ignored.
+			run_to_block(102);
+			assert!(!<Packets<Test>>::exists(id));
+		});
+	}
+
+	#[test]
+	fn reap_weight_should_scale_with_claims_and_recipients() {
+		new_test_ext().execute_with(|| {
+			let options = PacketOptions { recipients: vec![2, 3, 4], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 3, 100, options));
+			assert_eq!(Call::<Test>::reap(0).get_dispatch_info().weight, BASE_WEIGHT + 3 * RECIPIENT_WEIGHT);
+
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+			assert_eq!(Call::<Test>::reap(0).get_dispatch_info().weight, BASE_WEIGHT + (2 * 4 + 3) * RECIPIENT_WEIGHT);
+			assert_eq!(Call::<Test>::reap(1).get_dispatch_info().weight, BASE_WEIGHT);
+		});
+	}
+
+	#[test]
+	fn claim_should_fail_if_not_recipient() {
+		new_test_ext().execute_with(|| {
+			let options = PacketOptions { recipients: vec![2, 3], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, options));
+			let id = RedPackets::next_packet_id() - 1;
+
+			assert!(RedPackets::is_recipient(&id, &2));
+			assert_noop!(RedPackets::claim(Origin::signed(4), id), Error::<Test>::NotEligible);
+			assert_ok!(RedPackets::claim(Origin::signed(2), id));
+			assert_ok!(RedPackets::claim(Origin::signed(3), id));
+		});
+	}
+
+	#[test]
+	fn create_should_reserve_deposit_for_recipients() {
+		new_test_ext().execute_with(|| {
+			set_deposit(5, 1);
+			let options = PacketOptions { recipients: vec![2, 3, 4], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, options));
+			assert_eq!(RedPackets::packets(0).unwrap().deposit, 5 + 2 + 3);
+		});
+	}
+
+	#[test]
+	fn claim_with_proof_should_work() {
+		new_test_ext().execute_with(|| {
+			let leaves = [(2, 10), (3, 20), (4, 30)];
+			let (root, proofs) = merkle_tree(&leaves);
+			assert_ok!(RedPackets::create_merkle(Origin::signed(1), root, 60, 3, 100, immediate()));
+			let id = RedPackets::next_packet_id() - 1;
+
+			// Three leaves need proofs of two hashes.
+			assert_eq!(
+				Call::<Test>::claim_with_proof(id, 0, 10, proofs[0].clone()).get_dispatch_info().weight,
+				BASE_WEIGHT + PAYOUT_WEIGHT + 2 * HASH_WEIGHT
+			);
+
+			for (index, (&(user, amount), proof)) in leaves.iter().zip(proofs).enumerate() {
+				assert_ok!(RedPackets::claim_with_proof(Origin::signed(user), id, index as u32, amount, proof));
+			}
+
+			assert_eq!(RedPackets::claimed_bitmap(&id, &0), 0b111);
+			assert_eq!(RedPackets::packets(id).unwrap().unclaimed, 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&3), 300 + 20);
+			assert_eq!(balances::Module::<Test>::free_balance(&4), 400 + 30);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 60);
+		});
+	}
+
+	#[test]
+	fn claim_with_proof_should_fail_with_invalid_proof() {
+		new_test_ext().execute_with(|| {
+			let leaves = [(2, 10), (3, 20), (4, 30)];
+			let (root, proofs) = merkle_tree(&leaves);
+			assert_ok!(RedPackets::create_merkle(Origin::signed(1), root, 60, 3, 100, Default::default()));
+			let id = RedPackets::next_packet_id() - 1;
+
+			// Wrong amount, wrong account, wrong index and a proof of another leaf.
+			assert_noop!(
+				RedPackets::claim_with_proof(Origin::signed(2), id, 0, 20, proofs[0].clone()),
+				Error::<Test>::InvalidProof
+			);
+			assert_noop!(
+				RedPackets::claim_with_proof(Origin::signed(5), id, 0, 10, proofs[0].clone()),
+				Error::<Test>::InvalidProof
+			);
+			assert_noop!(
+				RedPackets::claim_with_proof(Origin::signed(2), id, 1, 10, proofs[0].clone()),
+				Error::<Test>::InvalidProof
+			);
+			assert_noop!(
+				RedPackets::claim_with_proof(Origin::signed(2), id, 0, 10, proofs[1].clone()),
+				Error::<Test>::InvalidProof
+			);
+			// Out of range index with the leaf's own proof.
+			assert_noop!(
+				RedPackets::claim_with_proof(Origin::signed(2), id, 4, 10, proofs[0].clone()),
+				Error::<Test>::InvalidProof
+			);
+		});
+	}
+
+	#[test]
+	fn claim_with_proof_should_fail_if_already_claimed() {
+		new_test_ext().execute_with(|| {
+			let leaves = [(2, 10), (3, 20)];
+			let (root, proofs) = merkle_tree(&leaves);
+			assert_ok!(RedPackets::create_merkle(Origin::signed(1), root, 30, 2, 100, Default::default()));
+			let id = RedPackets::next_packet_id() - 1;
+
+			assert_ok!(RedPackets::claim_with_proof(Origin::signed(2), id, 0, 10, proofs[0].clone()));
+			assert_noop!(
+				RedPackets::claim_with_proof(Origin::signed(2), id, 0, 10, proofs[0].clone()),
+				Error::<Test>::AlreadyClaimed
+			);
+			assert_noop!(RedPackets::claim(Origin::signed(3), id), Error::<Test>::UnsupportedKind);
+		});
+	}
+
+	#[test]
+	fn create_merkle_should_fail_with_secret_or_recipients() {
+		new_test_ext().execute_with(|| {
+			let (root, _) = merkle_tree(&[(2, 10), (3, 20)]);
+			let options = PacketOptions { secret: Some(42), ..Default::default() };
+			assert_noop!(
+				RedPackets::create_merkle(Origin::signed(1), root, 30, 2, 100, options),
+				Error::<Test>::UnsupportedKind
+			);
+			let options = PacketOptions { recipients: vec![2, 3], ..Default::default() };
+			assert_noop!(
+				RedPackets::create_merkle(Origin::signed(1), root, 30, 2, 100, options),
+				Error::<Test>::UnsupportedKind
+			);
+		});
+	}
+
+	#[test]
+	fn claim_with_secret_should_work() {
+		new_test_ext().execute_with(|| {
+			let options = PacketOptions { secret: Some(42), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, options));
+			let id = RedPackets::next_packet_id() - 1;
+
+			assert_noop!(RedPackets::claim(Origin::signed(2), id), Error::<Test>::InvalidSecret);
+			assert_ok!(RedPackets::claim_with_secret(Origin::signed(2), id, sign_secret(42, id, 2)));
+			assert_eq!(RedPackets::claim_of(&id, &2).map(|claim| claim.amount), Some(1));
+
+			// Signing with another keypair means not knowing the secret code.
+			assert_noop!(
+				RedPackets::claim_with_secret(Origin::signed(3), id, sign_secret(7, id, 3)),
+				Error::<Test>::InvalidSecret
+			);
+		});
+	}
+
+	#[test]
+	fn claim_with_secret_should_not_be_front_run() {
+		new_test_ext().execute_with(|| {
+			let options = PacketOptions { secret: Some(42), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, options));
+			let id = RedPackets::next_packet_id() - 1;
+
+			// Account 3 copies the signature of account 2 from the transaction pool.
+			let signature = sign_secret(42, id, 2);
+			assert_noop!(
+				RedPackets::claim_with_secret(Origin::signed(3), id, signature.clone()),
+				Error::<Test>::InvalidSecret
+			);
+			assert_ok!(RedPackets::claim_with_secret(Origin::signed(2), id, signature));
+		});
+	}
+
+	#[test]
+	fn claim_with_secret_should_not_be_replayed() {
+		new_test_ext().execute_with(|| {
+			let options = PacketOptions { secret: Some(42), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, options.clone()));
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 2, 100, options));
+
+			let signature = sign_secret(42, 0, 2);
+			assert_ok!(RedPackets::claim_with_secret(Origin::signed(2), 0, signature.clone()));
+			assert_noop!(
+				RedPackets::claim_with_secret(Origin::signed(2), 0, signature.clone()),
+				Error::<Test>::AlreadyClaimed
+			);
+			// The same secret code protects another RedPacket.
+			assert_noop!(
+				RedPackets::claim_with_secret(Origin::signed(2), 1, signature),
+				Error::<Test>::InvalidSecret
+			);
+		});
+	}
+
+	#[test]
+	fn redpacket_of_asset_should_work() {
+		new_test_ext().execute_with(|| {
+			set_deposit(5, 0);
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			let options = PacketOptions { asset: 1, ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 100, 2, 10, options));
+
+			// Funds are reserved in the asset, the deposit in the native currency.
+			assert_eq!(Assets::balance(1, 1), 800);
+			assert_eq!(Assets::reserved(1, 1), 200);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 5);
+
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+			assert_ok!(RedPackets::distribute(Origin::signed(1), 0));
+
+			assert_eq!(Assets::balance(1, 2), 100);
+			assert_eq!(Assets::balance(1, 3), 100);
+			assert_eq!(Assets::balance(1, 1), 800);
+			assert_eq!(Assets::reserved(1, 1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 5);
+		});
+	}
+
+	#[test]
+	fn immediate_redpacket_of_asset_should_pay_at_once() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			let options = PacketOptions { asset: 1, ..immediate() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 100, 2, 10, options));
+
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_eq!(Assets::balance(1, 2), 100);
+			assert_eq!(Assets::reserved(1, 1), 100);
+
+			run_to_block(12);
+			assert_eq!(Assets::reserved(1, 1), 0);
+			assert_eq!(Assets::balance(1, 1), 900);
+		});
+	}
+
+	#[test]
+	fn create_redpacket_of_asset_should_fail_if_insufficient_balance() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(2), 1_000));
+			let options = PacketOptions { asset: 1, ..Default::default() };
+			assert_noop!(
+				RedPackets::create(Origin::signed(1), 100, 2, 10, options),
+				Error::<Test>::InsufficientBalance
+			);
+
+			// The native deposit is checked besides the asset.
+			set_deposit(300, 0);
+			let options = PacketOptions { asset: 1, ..Default::default() };
+			assert_noop!(
+				RedPackets::create(Origin::signed(2), 100, 2, 10, options),
+				Error::<Test>::InsufficientBalance
+			);
+		});
+	}
+
+	#[test]
+	fn bundle_redpacket_should_pay_every_asset() {
+		new_test_ext().execute_with(|| {
+			set_deposit(1, 1);
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			let options = PacketOptions { bundle: vec![(1, 100)], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 10, options));
+
+			// One more slot of deposit for the bundled asset.
+			assert_eq!(RedPackets::packets(0).unwrap().deposit, 1 + 3 + 1);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 30 + 5);
+			assert_eq!(Assets::reserved(1, 1), 300);
+
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+			run_to_block(12);
+
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(Assets::balance(1, 2), 100);
+			assert_eq!(Assets::balance(1, 3), 100);
+			// The quota of the unclaimed slot is refunded.
+			assert_eq!(Assets::reserved(1, 1), 0);
+			assert_eq!(Assets::balance(1, 1), 800);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 5);
+		});
+	}
 
+	#[test]
+	fn automatic_settlement_should_count_bundled_assets() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			let options = PacketOptions { bundle: vec![(1, 100)], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 10, options));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+
+			// Every claim pays two assets, a single claim fits in the budget with closing.
+			run_to_block(11);
+			assert_eq!(RedPackets::distribution_progress(0), (1, 10));
+			assert_eq!(Assets::balance(1, 2), 100);
+			assert_eq!(Assets::balance(1, 3), 0);
+
+			run_to_block(12);
+			assert_eq!(RedPackets::packets(0).unwrap().closed_at, Some(12));
+			assert_eq!(Assets::balance(1, 3), 100);
+			assert_eq!(Assets::reserved(1, 1), 0);
+		});
 	}
-}
 
-#[cfg(test)]
-mod tests {
-	use super::*;
-	use balances::GenesisConfig;
-	use frame_support::{impl_outer_origin, assert_ok, assert_noop, parameter_types, weights::Weight};
-	use sp_core::H256;
-	// The testing primitives are very useful for avoiding having to work with signatures
-	// or public keys. `u64` is used as the `AccountId` and no `Signature`s are required.
-	use sp_runtime::{Perbill, traits::{BlakeTwo256, IdentityLookup}, testing::Header};
+	#[test]
+	fn immediate_bundle_claim_should_be_atomic() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			let options = PacketOptions { bundle: vec![(1, 100)], ..immediate() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 2, 10, options));
+
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(Assets::balance(1, 2), 100);
+
+			// The bundled asset is gone from the reserve, so nothing is paid at all.
+			<Assets as MultiReservableCurrency<u64>>::unreserve(1, &1, 100);
+			assert_noop!(RedPackets::claim(Origin::signed(3), 0), Error::<Test>::InsufficientBalance);
+			assert_eq!(balances::Module::<Test>::free_balance(&3), 300);
+			assert_eq!(Assets::balance(1, 3), 0);
+		});
+	}
 
-	impl_outer_origin! {
-		pub enum Origin for Test  {}
+	#[test]
+	fn create_bundle_redpacket_should_check_assets() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+
+			let options = PacketOptions { bundle: vec![(1, 100), (1, 100)], ..Default::default() };
+			assert_noop!(RedPackets::create(Origin::signed(1), 10, 2, 10, options), Error::<Test>::DuplicateAsset);
+
+			let options = PacketOptions { bundle: vec![(0, 100)], ..Default::default() };
+			assert_noop!(RedPackets::create(Origin::signed(1), 10, 2, 10, options), Error::<Test>::DuplicateAsset);
+
+			let options = PacketOptions { bundle: vec![(1, 0)], ..Default::default() };
+			assert_noop!(RedPackets::create(Origin::signed(1), 10, 2, 10, options), Error::<Test>::GreaterThanZero);
+
+			let options = PacketOptions { bundle: vec![(1, 1), (2, 1), (3, 1)], ..Default::default() };
+			assert_noop!(RedPackets::create(Origin::signed(1), 10, 2, 10, options), Error::<Test>::BundleTooLarge);
+
+			let options = PacketOptions { bundle: vec![(1, 100)], ..Default::default() };
+			assert_noop!(RedPackets::create_lucky(Origin::signed(1), 10, 2, 10, options), Error::<Test>::UnsupportedKind);
+
+			// Not enough of the bundled asset for every slot.
+			let options = PacketOptions { bundle: vec![(1, 600)], ..Default::default() };
+			assert_noop!(RedPackets::create(Origin::signed(1), 10, 2, 10, options), Error::<Test>::InsufficientBalance);
+		});
 	}
 
-	// For testing the module, we construct most of a mock runtime. This means
-	// first constructing a configuration type (`Test`) which `impl`s each of the
-	// configuration traits of modules we want to use.
-	#[derive(Clone, Eq, PartialEq)]
-	pub struct Test;
-	parameter_types! {
-		pub const BlockHashCount: u64 = 250;
-		pub const MaximumBlockWeight: Weight = 1024;
-		pub const MaximumBlockLength: u32 = 2 * 1024;
-		pub const AvailableBlockRatio: Perbill = Perbill::one();
+	#[test]
+	fn claim_should_be_free_for_new_accounts() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, immediate()));
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, immediate()));
+
+			assert_ok!(pre_dispatch_claim(11, Call::claim(0)));
+			assert_ok!(RedPackets::claim(Origin::signed(11), 0));
+			assert_eq!(balances::Module::<Test>::free_balance(&11), 10);
+			assert_eq!(RedPackets::last_free_claim(&11), Some(0));
+
+			// Within `FreeClaimInterval` the fee is charged.
+			assert_ok!(pre_dispatch_claim(11, Call::claim(1)));
+			assert_eq!(balances::Module::<Test>::free_balance(&11), 10 - 1);
+
+			// The next free claim after the interval.
+			run_to_block(5);
+			assert_ok!(pre_dispatch_claim(12, Call::claim(1)));
+			assert_eq!(RedPackets::last_free_claim(&12), Some(5));
+		});
 	}
-	impl system::Trait for Test {
-		type Origin = Origin;
-		type Index = u64;
-		type Call = ();
-		type BlockNumber = u64;
-		type Hash = H256;
-		type Hashing = BlakeTwo256;
-		type AccountId = u64;
-		type Lookup = IdentityLookup<Self::AccountId>;
-		type Header = Header;
-		type Event = ();
-		type BlockHashCount = BlockHashCount;
-		type MaximumBlockWeight = MaximumBlockWeight;
-		type AvailableBlockRatio = AvailableBlockRatio;
-		type MaximumBlockLength = MaximumBlockLength;
-		type Version = ();
-		type ModuleToIndex = ();
+
+	#[test]
+	fn failing_claim_should_not_be_free() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+
+			// An account without balance can not pay for a claim which would fail.
+			assert!(pre_dispatch_claim(11, Call::claim(7)).is_err());
+			assert!(pre_dispatch_claim(2, Call::claim(0)).is_ok());
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 - 1);
+
+			// Other calls pay as usual.
+			assert!(pre_dispatch_claim(11, Call::refund(0)).is_err());
+			assert_eq!(RedPackets::free_claims_in_block(), 0);
+		});
 	}
 
-	parameter_types! {
-		pub const TransferFee: u64 = 0;
-		pub const CreationFee: u64 = 0;
-		pub const ExistentialDeposit: u64 = 0;
+	#[test]
+	fn unpayable_claim_should_not_be_free() {
+		new_test_ext().execute_with(|| {
+			set_existential_deposit(10);
+			assert_ok!(RedPackets::create(Origin::signed(1), 5, 3, 100, Default::default()));
+
+			// The claim would fail with `BelowExistentialDeposit`, nothing is written for account 11.
+			assert!(pre_dispatch_claim(11, Call::claim(0)).is_err());
+			assert_eq!(RedPackets::last_free_claim(&11), None);
+			assert_eq!(RedPackets::free_claims_in_block(), 0);
+
+			let call = Call::claim_unsigned(0, 11, sign_claim(0, 11));
+			assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::Call.into());
+		});
 	}
-	impl balances::Trait for Test {
-		type Balance = u64;
-		type OnFreeBalanceZero =  ();
-		type OnNewAccount = ();
-		type Event = ();
-		type TransferPayment = ();
-		type DustRemoval = ();
-		type ExistentialDeposit = ExistentialDeposit;
-		type TransferFee = TransferFee;
-		type CreationFee = CreationFee;
+
+	#[test]
+	fn free_claims_should_be_limited_per_block() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 5, 100, Default::default()));
+
+			assert_ok!(pre_dispatch_claim(11, Call::claim(0)));
+			assert_ok!(pre_dispatch_claim(12, Call::claim(0)));
+			assert!(pre_dispatch_claim(13, Call::claim(0)).is_err());
+			assert_eq!(RedPackets::free_claims_in_block(), 2);
+
+			run_to_block(1);
+			assert_eq!(RedPackets::free_claims_in_block(), 0);
+			assert_ok!(pre_dispatch_claim(13, Call::claim(0)));
+		});
 	}
-	impl Trait for Test {
-		type Currency = balances::Module<Self>;
-		type Event = ();
-		type PacketId = u32;
+
+	#[test]
+	fn claim_unsigned_should_work() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, immediate()));
+
+			let call = Call::claim_unsigned(0, 11, sign_claim(0, 11));
+			let valid = RedPackets::validate_unsigned(&call).unwrap();
+			assert_eq!(valid.priority, 100);
+			assert_eq!(valid.provides, vec![(0u32, 11u64).encode()]);
+			assert_eq!(valid.longevity, 101);
+
+			assert_ok!(RedPackets::claim_unsigned(system::RawOrigin::None.into(), 0, 11, sign_claim(0, 11)));
+			assert_eq!(balances::Module::<Test>::free_balance(&11), 10);
+			assert_eq!(RedPackets::free_claims_in_block(), 1);
+
+			// The claim can not be replayed.
+			assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::Stale.into());
+			assert_noop!(
+				RedPackets::claim_unsigned(system::RawOrigin::None.into(), 0, 11, sign_claim(0, 11)),
+				Error::<Test>::AlreadyClaimed
+			);
+		});
 	}
-	type RedPackets = Module<Test>;
 
-	// This function basically just builds a genesis storage key/value store according to
-	// our desired mockup.
-	fn new_test_ext() -> sp_io::TestExternalities {
-		// system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+	#[test]
+	fn claim_unsigned_should_check_signature() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()));
+
+			// Signed for another claimer or another RedPacket.
+			for call in vec![
+				Call::claim_unsigned(0, 12, sign_claim(0, 11)),
+				Call::claim_unsigned(0, 11, sign_claim(1, 11)),
+			] {
+				assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::BadProof.into());
+			}
+			assert_noop!(
+				RedPackets::claim_unsigned(system::RawOrigin::None.into(), 0, 12, sign_claim(0, 11)),
+				Error::<Test>::InvalidSignature
+			);
+			assert!(RedPackets::claim_unsigned(Origin::signed(11), 0, 11, sign_claim(0, 11)).is_err());
+		});
+	}
+
+	#[test]
+	fn claim_unsigned_should_be_limited() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 5, 100, Default::default()));
+			let options = PacketOptions { secret: Some(42), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 5, 100, options));
+
+			// Secret codes need a signed claim.
+			let call = Call::claim_unsigned(1, 11, sign_claim(1, 11));
+			assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::Call.into());
+
+			assert_ok!(RedPackets::claim_unsigned(system::RawOrigin::None.into(), 0, 11, sign_claim(0, 11)));
+			assert_ok!(RedPackets::claim_unsigned(system::RawOrigin::None.into(), 0, 12, sign_claim(0, 12)));
+			let call = Call::claim_unsigned(0, 13, sign_claim(0, 13));
+			assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::ExhaustsResources.into());
+
+			// Blocks made without the pool are limited as well.
+			assert_noop!(
+				RedPackets::claim_unsigned(system::RawOrigin::None.into(), 0, 13, sign_claim(0, 13)),
+				Error::<Test>::FreeClaimsExhausted
+			);
+
+			run_to_block(1);
+			assert!(RedPackets::validate_unsigned(&call).is_ok());
+
+			// Expired claims are dropped.
+			run_to_block(101);
+			assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::Stale.into());
+		});
+	}
+
+	#[test]
+	fn runtime_api_queries_should_work() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 2, 10, Default::default()));
+			assert_ok!(RedPackets::create_lucky(Origin::signed(2), 30, 3, 10, Default::default()));
+			assert_ok!(RedPackets::create(Origin::signed(1), 5, 2, 10, Default::default()));
+
+			assert_eq!(RedPackets::packet(0).map(|packet| packet.total), Some(20));
+			assert!(RedPackets::packet(3).is_none());
+			assert_eq!(RedPackets::packets_by_owner(&1), vec![0, 2]);
+			assert_eq!(RedPackets::packets_by_owner(&3), Vec::<u32>::new());
+
+			assert_eq!(RedPackets::claimable_amount(0, &3), Some(10));
+			assert_eq!(RedPackets::claimable_amount(1, &3), Some(10));
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+			assert_eq!(RedPackets::claimable_amount(0, &3), None);
+			assert_eq!(RedPackets::claimable_amount(3, &3), None);
+
+			assert!(!RedPackets::is_distributable(0));
+			assert_ok!(RedPackets::claim(Origin::signed(4), 0));
+			assert!(RedPackets::is_distributable(0));
+			assert_ok!(RedPackets::distribute(Origin::signed(1), 0));
+			assert!(!RedPackets::is_distributable(0));
+
+			system::Module::<Test>::set_block_number(11);
+			assert!(RedPackets::is_distributable(2));
+			assert_eq!(RedPackets::claimable_amount(2, &3), None);
+		});
+	}
+
+	#[test]
+	fn indexes_should_follow_every_call() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 2, 10, Default::default()));
+			assert_indexes_consistent();
+			assert_ok!(RedPackets::create_lucky(Origin::signed(2), 20, 2, 10, immediate()));
+			assert_indexes_consistent();
+			let (root, proofs) = merkle_tree(&[(3, 5), (4, 5)]);
+			assert_ok!(RedPackets::create_merkle(Origin::signed(1), root, 10, 2, 10, Default::default()));
+			assert_indexes_consistent();
+			let options = PacketOptions { secret: Some(42), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(3), 1, 2, 10, options));
+			assert_indexes_consistent();
+			assert_eq!(RedPackets::packets_by_owner(&1), vec![0, 2]);
+
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+			assert_indexes_consistent();
+			assert_ok!(RedPackets::claim(Origin::signed(3), 1));
+			assert_indexes_consistent();
+			assert_ok!(RedPackets::claim_with_proof(Origin::signed(3), 2, 0, 5, proofs[0].clone()));
+			assert_indexes_consistent();
+			assert_ok!(RedPackets::claim_with_secret(Origin::signed(4), 3, sign_secret(42, 3, 4)));
+			assert_indexes_consistent();
+			assert_ok!(RedPackets::claim_unsigned(system::RawOrigin::None.into(), 0, 11, sign_claim(0, 11)));
+			assert_indexes_consistent();
+			assert_eq!(RedPackets::packets_claimed_by(&3), vec![0, 1, 2]);
+
+			assert_ok!(RedPackets::distribute(Origin::signed(1), 0));
+			assert_indexes_consistent();
+			system::Module::<Test>::set_block_number(11);
+			assert_ok!(RedPackets::refund(Origin::signed(2), 1));
+			assert_indexes_consistent();
+			RedPackets::on_initialize(11);
+			assert_indexes_consistent();
+
+			// Reaping prunes both indexes.
+			run_to_block(25);
+			for id in 0..4 {
+				assert_ok!(RedPackets::reap(Origin::signed(5), id));
+				assert_indexes_consistent();
+			}
+			for account in 0..20 {
+				assert!(RedPackets::packets_by_owner(&account).is_empty());
+				assert!(RedPackets::packets_claimed_by(&account).is_empty());
+			}
+		});
+	}
+
+	#[test]
+	fn runtime_upgrade_should_build_indexes() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 2, 10, Default::default()));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			<PacketsByOwner<Test>>::remove(&1, &0);
+			<ClaimsByAccount<Test>>::remove(&2, &0);
+
+			RedPackets::on_runtime_upgrade();
+
+			assert_eq!(RedPackets::packets_by_owner(&1), vec![0]);
+			assert_eq!(RedPackets::packets_claimed_by(&2), vec![0]);
+			assert_indexes_consistent();
+		});
+	}
+
+	#[test]
+	fn unknown_redpacket_should_not_be_found() {
+		new_test_ext().execute_with(|| {
+			assert_noop!(RedPackets::claim(Origin::signed(2), 999), Error::<Test>::PacketNotFound);
+			assert_noop!(
+				RedPackets::claim_with_secret(Origin::signed(2), 999, sign_secret(42, 999, 2)),
+				Error::<Test>::PacketNotFound
+			);
+			assert_noop!(
+				RedPackets::claim_with_proof(Origin::signed(2), 999, 0, 1, Vec::new()),
+				Error::<Test>::PacketNotFound
+			);
+			assert_noop!(
+				RedPackets::claim_unsigned(system::RawOrigin::None.into(), 999, 11, sign_claim(999, 11)),
+				Error::<Test>::PacketNotFound
+			);
+			assert_noop!(RedPackets::distribute(Origin::signed(1), 999), Error::<Test>::PacketNotFound);
+			assert_noop!(RedPackets::refund(Origin::signed(1), 999), Error::<Test>::PacketNotFound);
+			assert_noop!(RedPackets::reap(Origin::signed(1), 999), Error::<Test>::PacketNotFound);
+
+			assert_eq!(RedPackets::packet(999), None);
+			assert!(RedPackets::claims(999).is_empty());
+		});
+	}
+
+	#[test]
+	fn read_api_should_expose_packets_and_claims() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 2, 10, Default::default()));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+
+			let packet = RedPackets::packet(0).unwrap();
+			assert_eq!(packet.owner, 1);
+			assert_eq!(packet.total, 20);
+			assert_eq!(packet.unclaimed, 10);
+			assert_eq!(packet.claimed, 1);
+			assert_eq!(RedPackets::claims(0), vec![(2, 10)]);
+			assert_eq!(RedPackets::claim_of(&0, &2).map(|claim| claim.block), Some(0));
+		});
+	}
+
+	#[test]
+	fn cancel_should_remove_unclaimed_redpacket() {
+		new_test_ext().execute_with(|| {
+			set_deposit(5, 1);
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 2, 100, Default::default()));
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 20 + 7);
+
+			system::Module::<Test>::set_block_number(1);
+			assert_noop!(RedPackets::cancel(Origin::signed(2), 0), Error::<Test>::NotOwner);
+			assert_ok!(RedPackets::cancel(Origin::signed(1), 0));
+			assert_eq!(redpacket_events(), vec![RawEvent::Cancelled(0, 1, 20)]);
+
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100);
+			assert_eq!(RedPackets::packet(0), None);
+			assert!(RedPackets::packets_by_owner(&1).is_empty());
+			assert_noop!(RedPackets::claim(Origin::signed(2), 0), Error::<Test>::PacketNotFound);
+			assert_noop!(RedPackets::cancel(Origin::signed(1), 0), Error::<Test>::PacketNotFound);
+
+			// The expiry of a cancelled RedPacket is dropped by the automatic settlement.
+			run_to_block(101);
+			assert!(expirations(100).is_empty());
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100);
+		});
+	}
+
+	#[test]
+	fn cancel_weight_should_scale_with_recipients() {
+		new_test_ext().execute_with(|| {
+			let options = PacketOptions { recipients: vec![2, 3, 4], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 1, 3, 100, options));
+			assert_eq!(
+				Call::<Test>::cancel(0).get_dispatch_info().weight,
+				BASE_WEIGHT + 2 * PAYOUT_WEIGHT + 3 * RECIPIENT_WEIGHT
+			);
+			assert_eq!(Call::<Test>::cancel(1).get_dispatch_info().weight, BASE_WEIGHT);
+		});
+	}
+
+	#[test]
+	fn cancel_should_return_every_asset_of_the_bundle() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(Assets::issue(Origin::signed(1), 1_000));
+			let options = PacketOptions { bundle: vec![(1, 100)], ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 10, options));
+			assert_eq!(Assets::reserved(1, 1), 300);
+
+			assert_ok!(RedPackets::cancel(Origin::signed(1), 0));
+
+			assert_eq!(Assets::reserved(1, 1), 0);
+			assert_eq!(Assets::balance(1, 1), 1_000);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+		});
+	}
+
+	#[test]
+	fn cancel_should_settle_with_existing_claimers() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+
+			assert_ok!(RedPackets::cancel(Origin::signed(1), 0));
+
+			let events = redpacket_events();
+			assert_eq!(
+				&events[events.len() - 2..],
+				&[RawEvent::Distributed(0, 1, 10), RawEvent::Refunded(0, 1, 20)][..]
+			);
+			let packet = RedPackets::packet(0).unwrap();
+			assert!(packet.distributed);
+			assert_eq!(packet.closed_at, Some(1));
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+			assert_noop!(RedPackets::claim(Origin::signed(3), 0), Error::<Test>::AlreadyClosed);
+			assert_noop!(RedPackets::cancel(Origin::signed(1), 0), Error::<Test>::AlreadyClosed);
+		});
+	}
+
+	#[test]
+	fn cancel_should_stop_claims_while_paged() {
+		new_test_ext().execute_with(|| {
+			RedPackets::create(Origin::signed(4), 10, 5, 100, Default::default()).ok();
+			for user in 10..13 {
+				RedPackets::claim(Origin::signed(user), 0).ok();
+			}
+
+			assert_ok!(RedPackets::cancel(Origin::signed(4), 0));
+			assert_eq!(RedPackets::distribution_progress(0), (2, 20));
+			assert_noop!(RedPackets::claim(Origin::signed(13), 0), Error::<Test>::AlreadyClosed);
+			assert!(RedPackets::is_distributable(0));
+
+			assert_ok!(RedPackets::distribute(Origin::signed(4), 0));
+			assert!(RedPackets::packet(0).unwrap().distributed);
+			assert_eq!(balances::Module::<Test>::free_balance(&12), 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&4), 400 - 30);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&4), 0);
+		});
+	}
+
+	#[test]
+	fn genesis_should_create_redpackets() {
 		let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
 		GenesisConfig::<Test> {
-			balances: vec![
-				(1, 100),
-				(2, 200),
-				(3, 300),
-				(4, 400),
-				(5, 1),
-			],
-			vesting: vec![]
+			balances: vec![(1, 100), (2, 200), (3, 300)],
+			vesting: vec![],
+		}.assimilate_storage(&mut t).unwrap();
+		super::GenesisConfig::<Test> {
+			packets: vec![(1, 10, 3, 20, vec![2, 3]), (2, 5, 2, 30, vec![])],
 		}.assimilate_storage(&mut t).unwrap();
-		t.into()
-	}
 
+		sp_io::TestExternalities::from(t).execute_with(|| {
+			assert_eq!(RedPackets::next_packet_id(), 2);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 30);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&2), 10);
+
+			let packet = RedPackets::packet(0).unwrap();
+			assert_eq!(packet.expires_at, 20);
+			assert_eq!(packet.unclaimed, 10);
+			assert_eq!(RedPackets::claims(0), vec![(2, 10), (3, 10)]);
+			assert_eq!(RedPackets::packets_by_owner(&2), vec![1]);
+			assert_eq!(RedPackets::packets_claimed_by(&2), vec![0]);
+			assert_eq!(expirations(30), vec![1]);
+			assert_indexes_consistent();
+
+			assert_ok!(RedPackets::create(Origin::signed(3), 1, 1, 10, Default::default()));
+			assert_eq!(RedPackets::packets_by_owner(&3), vec![2]);
+
+			run_to_block(21);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 - 10 + 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 20);
+		});
+	}
 
 	#[test]
-	fn create_redpacket_should_work() {
+	fn claim_should_fail_after_deadline() {
 		new_test_ext().execute_with(|| {
-			assert_ok!(RedPackets::create(Origin::signed(1), 1, 5, 100));
+			system::Module::<Test>::set_block_number(1);
+			Timestamp::set_timestamp(1000);
+			let options = PacketOptions { deadline: Some(1060), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 0, options));
+
+			// Settlement is scheduled at the block expected to pass the deadline.
+			assert_eq!(RedPackets::packet(0).unwrap().expires_at, 1 + 60 / 6 + 1);
+			assert_eq!(expirations(12), vec![0]);
+
+			Timestamp::set_timestamp(1060);
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert!(!RedPackets::is_distributable(0));
+			assert_noop!(RedPackets::refund(Origin::signed(1), 0), Error::<Test>::NotExpired);
+
+			// Still in the same block, only the time has passed.
+			Timestamp::set_timestamp(1061);
+			assert_noop!(RedPackets::claim(Origin::signed(3), 0), Error::<Test>::Expired);
+			assert_eq!(RedPackets::claimable_amount(0, &3), None);
+			assert!(RedPackets::is_distributable(0));
+			assert_ok!(RedPackets::distribute(Origin::signed(1), 0));
+
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
 		});
 	}
 
 	#[test]
-	fn create_redpacket_should_fail_if_insufficient_balance() {
+	fn create_should_fail_if_deadline_passed() {
 		new_test_ext().execute_with(|| {
-			assert_noop!(RedPackets::create(Origin::signed(5), 1, 5, 100), Error::<Test>::InsufficientBalance);
+			Timestamp::set_timestamp(1000);
+			let options = PacketOptions { deadline: Some(1000), ..Default::default() };
+			assert_noop!(
+				RedPackets::create(Origin::signed(1), 10, 3, 100, options),
+				Error::<Test>::DeadlinePassed
+			);
+			// Without a deadline the number of blocks is required.
+			assert_noop!(
+				RedPackets::create(Origin::signed(1), 10, 3, 0, Default::default()),
+				Error::<Test>::GreaterThanZero
+			);
 		});
 	}
 
 	#[test]
-	fn create_redpacket_should_failed_with_invalid_arguments() {
+	fn deadline_settlement_should_wait_for_slow_time() {
 		new_test_ext().execute_with(|| {
-			assert_noop!(RedPackets::create(Origin::signed(1), 0, 5, 100), Error::<Test>::GreaterThanZero);
-			assert_noop!(RedPackets::create(Origin::signed(1), 1, 0, 100), Error::<Test>::GreaterThanZero);
-			assert_noop!(RedPackets::create(Origin::signed(1), 1, 5, 0), Error::<Test>::GreaterThanZero);
+			system::Module::<Test>::set_block_number(1);
+			Timestamp::set_timestamp(1000);
+			let options = PacketOptions { deadline: Some(1060), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 0, options));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+
+			// Blocks come twice as fast as expected, the deadline is not passed at block 12.
+			run_to_block_with_time(13, 3);
+			let packet = RedPackets::packet(0).unwrap();
+			assert_eq!(packet.closed_at, None);
+			assert_eq!(packet.expires_at, 13 + (1060 - 1033) / 6 + 1);
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+
+			run_to_block_with_time(23, 3);
+			assert_eq!(RedPackets::packet(0).unwrap().closed_at, None);
+
+			// `on_initialize` of block 24 sees the timestamp 1066 of block 23.
+			run_to_block_with_time(24, 3);
+			assert_eq!(RedPackets::packet(0).unwrap().closed_at, Some(24));
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&3), 300 + 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 20);
+			assert!(expirations(23).is_empty());
 		});
 	}
 
 	#[test]
-	fn claim_should_work() {
+	fn deadline_should_not_wait_for_slow_blocks() {
 		new_test_ext().execute_with(|| {
-			RedPackets::create(Origin::signed(1), 1, 5, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			assert_ok!(RedPackets::claim(Origin::signed(2), id));
-			assert_ok!(RedPackets::claim(Origin::signed(3), id));
+			system::Module::<Test>::set_block_number(1);
+			Timestamp::set_timestamp(1000);
+			let options = PacketOptions { deadline: Some(1060), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 0, options));
+
+			// Blocks take twice as long as expected, block 7 has the timestamp 1072.
+			run_to_block_with_time(7, 12);
+			assert_noop!(RedPackets::claim(Origin::signed(2), 0), Error::<Test>::Expired);
+			assert_ok!(RedPackets::refund(Origin::signed(1), 0));
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100);
 		});
 	}
 
 	#[test]
-	fn claim_should_fail_if_expired() {
+	fn claim_should_fail_before_opening_block() {
 		new_test_ext().execute_with(|| {
 			system::Module::<Test>::set_block_number(1);
-			RedPackets::create(Origin::signed(1), 1, 5, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			system::Module::<Test>::set_block_number(102);
-			assert_noop!(RedPackets::claim(Origin::signed(2), id), Error::<Test>::Expired);			
+			let options = PacketOptions { opens_at: Some(Opening::Block(5)), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, options));
+
+			// The funds are reserved before the RedPacket opens.
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 30);
+			assert_noop!(RedPackets::claim(Origin::signed(2), 0), Error::<Test>::NotYetOpen);
+			assert_eq!(RedPackets::claimable_amount(0, &2), None);
+
+			system::Module::<Test>::set_block_number(5);
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
 		});
 	}
 
 	#[test]
-	fn claim_should_fail_if_unavailable(){
+	fn claim_should_fail_before_opening_moment() {
 		new_test_ext().execute_with(|| {
-			RedPackets::create(Origin::signed(1), 1, 2, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			RedPackets::claim(Origin::signed(2), id).ok();
-			RedPackets::claim(Origin::signed(3), id).ok();
-			assert_noop!(RedPackets::claim(Origin::signed(4), id), Error::<Test>::Unavailable);
+			Timestamp::set_timestamp(1000);
+			let options = PacketOptions { opens_at: Some(Opening::Moment(1100)), ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, options));
+
+			Timestamp::set_timestamp(1099);
+			assert_noop!(RedPackets::claim(Origin::signed(2), 0), Error::<Test>::NotYetOpen);
+			let call = Call::claim_unsigned(0, 11, sign_claim(0, 11));
+			assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::Future.into());
+
+			Timestamp::set_timestamp(1100);
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert!(RedPackets::validate_unsigned(&call).is_ok());
 		});
 	}
 
 	#[test]
-	fn claim_should_fail_if_already_claimed() {
+	fn create_should_fail_if_opening_after_expiry() {
 		new_test_ext().execute_with(|| {
-			RedPackets::create(Origin::signed(1), 1, 2, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			RedPackets::claim(Origin::signed(2), id).ok();
-			assert_noop!(RedPackets::claim(Origin::signed(2), id), Error::<Test>::AlreadyClaimed);
+			let options = PacketOptions { opens_at: Some(Opening::Block(101)), ..Default::default() };
+			assert_noop!(
+				RedPackets::create(Origin::signed(1), 10, 3, 100, options),
+				Error::<Test>::InvalidOpening
+			);
+
+			let options = PacketOptions {
+				deadline: Some(1000),
+				opens_at: Some(Opening::Moment(1001)),
+				..Default::default()
+			};
+			assert_noop!(
+				RedPackets::create(Origin::signed(1), 10, 3, 0, options),
+				Error::<Test>::InvalidOpening
+			);
 		});
 	}
 
 	#[test]
-	fn distribute_should_work(){
+	fn claim_should_fail_below_minimum_balance() {
 		new_test_ext().execute_with(|| {
-			RedPackets::create(Origin::signed(1), 1, 2, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			RedPackets::claim(Origin::signed(2), id).ok();
-			RedPackets::claim(Origin::signed(3), id).ok();
-			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+			let eligibility = Eligibility { min_balance: 200, ..Default::default() };
+			let options = PacketOptions { eligibility, ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(4), 10, 3, 100, options));
 
-			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 2);
-			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 1);
-			assert_eq!(balances::Module::<Test>::free_balance(&3), 300 + 1);
+			assert_noop!(RedPackets::claim(Origin::signed(1), 0), Error::<Test>::BalanceTooLow);
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
 		});
 	}
 
 	#[test]
-	fn distribute_should_fail_if_already_distributed(){
+	fn claim_should_fail_for_new_accounts() {
 		new_test_ext().execute_with(|| {
-			RedPackets::create(Origin::signed(1), 1, 2, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			RedPackets::claim(Origin::signed(2), id).ok();
-			RedPackets::claim(Origin::signed(3), id).ok();
-			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
-			assert_noop!(RedPackets::distribute(Origin::signed(1), id), Error::<Test>::AlreadyDistributed);
+			let eligibility = Eligibility { min_age: 10, ..Default::default() };
+			let options = PacketOptions { eligibility, ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(4), 10, 3, 100, options));
+
+			system::Module::<Test>::set_block_number(5);
+			assert_ok!(balances::Module::<Test>::transfer(Origin::signed(3), 20, 50));
+			assert_eq!(RedPackets::account_created_at(&20), Some(5));
+
+			system::Module::<Test>::set_block_number(14);
+			assert_noop!(RedPackets::claim(Origin::signed(20), 0), Error::<Test>::AccountTooNew);
+			// Accounts without any balance do not exist yet.
+			assert_noop!(RedPackets::claim(Origin::signed(30), 0), Error::<Test>::AccountTooNew);
+			// Accounts of the genesis are as old as the chain.
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+
+			system::Module::<Test>::set_block_number(15);
+			assert_ok!(RedPackets::claim(Origin::signed(20), 0));
 		});
 	}
 
 	#[test]
-	fn distribute_should_fail_if_not_owner() {
+	fn accounts_before_age_tracking_should_be_as_old_as_the_tracking() {
 		new_test_ext().execute_with(|| {
-			RedPackets::create(Origin::signed(1), 1, 2, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			RedPackets::claim(Origin::signed(2), id).ok();
-			RedPackets::claim(Origin::signed(3), id).ok();
-			assert_noop!(RedPackets::distribute(Origin::signed(4), id), Error::<Test>::NotOwner);
+			let eligibility = Eligibility { min_age: 10, ..Default::default() };
+			let options = PacketOptions { eligibility, ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(4), 10, 3, 100, options));
+
+			system::Module::<Test>::set_block_number(20);
+			RedPackets::on_runtime_upgrade();
+			assert_eq!(RedPackets::age_tracking_since(), Some(20));
+
+			system::Module::<Test>::set_block_number(29);
+			assert_noop!(RedPackets::claim(Origin::signed(2), 0), Error::<Test>::AccountTooNew);
+
+			// Later upgrades keep tracking since the first one.
+			RedPackets::on_runtime_upgrade();
+			assert_eq!(RedPackets::age_tracking_since(), Some(20));
+
+			system::Module::<Test>::set_block_number(30);
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
 		});
 	}
 
 	#[test]
-	fn distribute_should_fail_if_not_expired_and_with_remaining_amount() {
+	fn reaped_accounts_should_be_forgotten() {
 		new_test_ext().execute_with(|| {
-			RedPackets::create(Origin::signed(1), 1, 2, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			RedPackets::claim(Origin::signed(2), id).ok();
-			assert_noop!(RedPackets::distribute(Origin::signed(1), id), Error::<Test>::CanNotBeDistributed);
+			set_existential_deposit(10);
+			assert_ok!(RedPackets::create(Origin::signed(4), 10, 3, 100, Default::default()));
+
+			system::Module::<Test>::set_block_number(5);
+			assert_ok!(balances::Module::<Test>::transfer(Origin::signed(3), 20, 50));
+			assert_ok!(RedPackets::claim(Origin::signed(20), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(1), 0));
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 1, 100, Default::default()));
+
+			// Account 20 is reaped.
+			assert_ok!(balances::Module::<Test>::transfer(Origin::signed(20), 3, 50));
+			assert_eq!(RedPackets::account_created_at(&20), None);
+			assert_eq!(RedPackets::claims_in_period(&20), (0, 0));
+
+			// Account 1 keeps its reserve.
+			assert_ok!(balances::Module::<Test>::transfer(Origin::signed(1), 3, 90));
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 10);
+			assert_eq!(RedPackets::claims_in_period(&1), (0, 1));
 		});
 	}
 
 	#[test]
-	fn distribute_should_work_if_not_expired_and_no_remaining_amount() {
+	fn claim_should_fail_after_too_many_claims_in_period() {
 		new_test_ext().execute_with(|| {
 			system::Module::<Test>::set_block_number(1);
-			RedPackets::create(Origin::signed(1), 1, 2, 100).ok();
-			let id = RedPackets::next_packet_id() - 1;
-			RedPackets::claim(Origin::signed(2), id).ok();
-			RedPackets::claim(Origin::signed(3), id).ok();
-			system::Module::<Test>::set_block_number(102);
-
-			assert_ok!(RedPackets::distribute(Origin::signed(1), id));
+			assert_ok!(RedPackets::create(Origin::signed(4), 10, 3, 100, Default::default()));
+			assert_ok!(RedPackets::create(Origin::signed(4), 10, 3, 100, Default::default()));
+			let eligibility = Eligibility { max_claims_per_period: 2, ..Default::default() };
+			let options = PacketOptions { eligibility, ..Default::default() };
+			assert_ok!(RedPackets::create(Origin::signed(4), 10, 3, 100, options));
+
+			// Claims from RedPackets without rules count as well.
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 1));
+			assert_noop!(RedPackets::claim(Origin::signed(2), 2), Error::<Test>::TooManyClaims);
+			assert_ok!(RedPackets::claim(Origin::signed(3), 2));
+
+			// A new period starts at block 10.
+			system::Module::<Test>::set_block_number(10);
+			assert_ok!(RedPackets::claim(Origin::signed(2), 2));
+			assert_eq!(RedPackets::claims_in_period(&2), (10, 1));
 		});
 	}
 
-}
+	#[test]
+	fn owner_should_not_claim_own_redpacket() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()));
 
+			assert_noop!(RedPackets::claim(Origin::signed(1), 0), Error::<Test>::OwnerCannotClaim);
+			assert_eq!(RedPackets::claimable_amount(0, &1), None);
+			let call = Call::claim_unsigned(0, 1, sign_claim(0, 1));
+			assert_eq!(RedPackets::validate_unsigned(&call), InvalidTransaction::Call.into());
+		});
+	}
 
+	#[test]
+	fn distribution_events_should_add_up_to_total() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			assert_ok!(RedPackets::claim(Origin::signed(3), 0));
+			system::Module::<Test>::set_block_number(101);
+			assert_ok!(RedPackets::distribute(Origin::signed(1), 0));
+
+			assert_eq!(redpacket_events(), vec![
+				RawEvent::Created(0, 1, 0, 30, 3),
+				RawEvent::Claimed(0, 2, 10),
+				RawEvent::Claimed(0, 3, 10),
+				RawEvent::Distributed(0, 1, 20),
+				RawEvent::Refunded(0, 1, 10),
+			]);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 20);
+		});
+	}
 
+	#[test]
+	fn legacy_owner_claims_should_be_refunded() {
+		new_test_ext().execute_with(|| {
+			system::Module::<Test>::set_block_number(1);
+			assert_ok!(RedPackets::create(Origin::signed(1), 10, 3, 100, Default::default()));
+			assert_ok!(RedPackets::claim(Origin::signed(2), 0));
+			// A claim of the creator recorded before they were forbidden.
+			let packet = RedPackets::packet(0).unwrap();
+			assert_ok!(RedPackets::record_claim(1, 0, packet, 10));
+
+			system::Module::<Test>::set_block_number(101);
+			assert_ok!(RedPackets::distribute(Origin::signed(1), 0));
+
+			let events = redpacket_events();
+			assert_eq!(
+				&events[events.len() - 2..],
+				&[RawEvent::Distributed(0, 1, 10), RawEvent::Refunded(0, 1, 20)][..]
+			);
+			assert_eq!(balances::Module::<Test>::free_balance(&1), 100 - 10);
+			assert_eq!(balances::Module::<Test>::free_balance(&2), 200 + 10);
+			assert_eq!(balances::Module::<Test>::reserved_balance(&1), 0);
+		});
+	}
+}
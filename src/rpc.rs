@@ -0,0 +1,109 @@
+//! RPC methods querying RedPackets through `RedPacketApi`.
+
+use std::sync::Arc;
+
+use codec::Codec;
+use jsonrpc_core::{Error as RpcError, ErrorCode, IoHandler, Result};
+use jsonrpc_derive::rpc;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::{Block as BlockT, ProvideRuntimeApi}};
+use redpacket_runtime::{opaque::Block, AccountId, Balance, PacketId, PacketInfo, RedPacketApi as RedPacketRuntimeApi};
+
+/// Error code of a failed runtime call.
+const RUNTIME_ERROR: i64 = 1;
+
+/// RedPacket RPC methods.
+#[rpc]
+pub trait RedPacketApi<BlockHash, AccountId, PacketId, Balance, Packet> {
+	/// The RedPacket `id`, if it was created and not reaped yet.
+	#[rpc(name = "redpacket_packet")]
+	fn packet(&self, id: PacketId, at: Option<BlockHash>) -> Result<Option<Packet>>;
+
+	/// Amount `who` receives by claiming from the RedPacket `id` now, if it can claim at all.
+	#[rpc(name = "redpacket_claimableAmount")]
+	fn claimable_amount(&self, id: PacketId, who: AccountId, at: Option<BlockHash>) -> Result<Option<Balance>>;
+
+	/// Ids of the RedPackets created by `owner`.
+	#[rpc(name = "redpacket_packetsByOwner")]
+	fn packets_by_owner(&self, owner: AccountId, at: Option<BlockHash>) -> Result<Vec<PacketId>>;
+
+	/// Whether the owner of the RedPacket `id` can distribute it now.
+	#[rpc(name = "redpacket_isDistributable")]
+	fn is_distributable(&self, id: PacketId, at: Option<BlockHash>) -> Result<bool>;
+}
+
+/// Implements `RedPacketApi` by calling the runtime of a client.
+pub struct RedPacket<C, B> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<B>,
+}
+
+impl<C, B> RedPacket<C, B> {
+	/// Create new `RedPacket` with the given reference to the client.
+	pub fn new(client: Arc<C>) -> Self {
+		RedPacket { client, _marker: Default::default() }
+	}
+}
+
+/// Map an error of a runtime call to an RPC error.
+fn runtime_error(e: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: "Unable to query RedPackets.".into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+impl<C, B, AccountId, PacketId, Balance, Packet> RedPacketApi<<B as BlockT>::Hash, AccountId, PacketId, Balance, Packet>
+	for RedPacket<C, B>
+where
+	B: BlockT,
+	C: Send + Sync + 'static,
+	C: ProvideRuntimeApi,
+	C: HeaderBackend<B>,
+	C::Api: RedPacketRuntimeApi<B, AccountId, PacketId, Balance, Packet>,
+	AccountId: Codec,
+	PacketId: Codec,
+	Balance: Codec,
+	Packet: Codec,
+{
+	fn packet(&self, id: PacketId, at: Option<<B as BlockT>::Hash>) -> Result<Option<Packet>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.packet(&at, id).map_err(runtime_error)
+	}
+
+	fn claimable_amount(
+		&self,
+		id: PacketId,
+		who: AccountId,
+		at: Option<<B as BlockT>::Hash>,
+	) -> Result<Option<Balance>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.claimable_amount(&at, id, who).map_err(runtime_error)
+	}
+
+	fn packets_by_owner(&self, owner: AccountId, at: Option<<B as BlockT>::Hash>) -> Result<Vec<PacketId>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.packets_by_owner(&at, owner).map_err(runtime_error)
+	}
+
+	fn is_distributable(&self, id: PacketId, at: Option<<B as BlockT>::Hash>) -> Result<bool> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.is_distributable(&at, id).map_err(runtime_error)
+	}
+}
+
+/// Create the RPC extensions of a full node.
+pub fn create_full<C>(client: Arc<C>) -> IoHandler<sc_rpc::Metadata>
+where
+	C: ProvideRuntimeApi + HeaderBackend<Block> + Send + Sync + 'static,
+	C::Api: RedPacketRuntimeApi<Block, AccountId, PacketId, Balance, PacketInfo>,
+{
+	let mut io = IoHandler::default();
+	io.extend_with(RedPacketApi::to_delegate(RedPacket::new(client)));
+	io
+}
@@ -73,6 +73,9 @@ macro_rules! new_full_start {
 				import_setup = Some((grandpa_block_import, grandpa_link));
 
 				Ok(import_queue)
+			})?
+			.with_rpc_extensions(|client, _pool, _backend, _fetcher, _remote_blockchain| {
+				Ok(crate::rpc::create_full(client))
 			})?;
 
 		(builder, import_setup, inherent_data_providers)